@@ -0,0 +1,100 @@
+//! Measures the parser throughput on large synthetic `birdc` outputs.
+//!
+//! Run via `cargo bench --bench parse`; criterion isn't used,
+//! so the numbers are plain averages over a fixed number of runs.
+
+use bird2topo::gather::build_graph;
+use bird2topo::parser::{parse_nested_blocks, parse_topology, Topology};
+use std::fmt::Write;
+use std::time::Instant;
+
+/// Generates the output of a single area with `n` routers, each of them
+/// linked to its successor in a ring (and to a router further away),
+/// with a stubnet per router and a transit network per 10 routers.
+fn generate(n: usize) -> String {
+    let rid = |i: usize| format!("10.{}.{}.{}", i >> 16 & 0xff, i >> 8 & 0xff, i & 0xff);
+    let net = |i: usize| format!("172.{}.{}.0/24", 16 + (i >> 8 & 0xf), i & 0xff);
+    let mut s = String::from("BIRD v2.0.7 ready.\n\narea 0.0.0.0\n\n");
+    for i in 0..n {
+        writeln!(s, "\trouter {}", rid(i)).unwrap();
+        writeln!(s, "\t\tdistance {}", i % 255).unwrap();
+        writeln!(s, "\t\trouter {} metric 10", rid((i + 1) % n)).unwrap();
+        writeln!(s, "\t\trouter {} metric 10", rid((i + n - 1) % n)).unwrap();
+        writeln!(s, "\t\trouter {} metric 100", rid((i + n / 2) % n)).unwrap();
+        writeln!(s, "\t\tnetwork {} metric 10", net(i / 10)).unwrap();
+        writeln!(
+            s,
+            "\t\tstubnet 192.168.{}.{}/32 metric 0",
+            i >> 8 & 0xff,
+            i & 0xff
+        )
+        .unwrap();
+        if i % 100 == 0 {
+            writeln!(s, "\t\texternal 0.0.0.0/0 metric2 10000 tag 0x00000064").unwrap();
+        }
+        s.push('\n');
+    }
+    for j in 0..n.div_ceil(10) {
+        writeln!(s, "\tnetwork {}", net(j)).unwrap();
+        writeln!(s, "\t\tdr {}", rid(j * 10)).unwrap();
+        writeln!(s, "\t\tdistance 10").unwrap();
+        for i in (j * 10)..std::cmp::min(j * 10 + 10, n) {
+            writeln!(s, "\t\trouter {}", rid(i)).unwrap();
+        }
+        s.push('\n');
+    }
+    s
+}
+
+fn bench(name: &str, input: &str, runs: u32, mut f: impl FnMut()) {
+    // warm up
+    f();
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    let per_run = start.elapsed() / runs;
+    let mib_per_sec = input.len() as f64 / per_run.as_secs_f64() / (1024.0 * 1024.0);
+    println!(
+        "{:<32} {:>10.3} ms/run {:>10.1} MiB/s",
+        name,
+        per_run.as_secs_f64() * 1000.0,
+        mib_per_sec
+    );
+}
+
+fn main() {
+    for &(n, runs) in &[(1_000, 50), (10_000, 5)] {
+        let input = generate(n);
+        let outputs = vec![input.clone()];
+        println!("{} routers ({} KiB):", n, input.len() / 1024);
+        bench("parse_nested_blocks", &input, runs, || {
+            parse_nested_blocks(&input).unwrap();
+        });
+        bench("parse_topology", &input, runs, || {
+            parse_topology(Topology::new(), &input).unwrap();
+        });
+        let topo = parse_topology(Topology::new(), &input).unwrap();
+        bench("RouterData::get_details", &input, runs, || {
+            for area in topo.areas.values() {
+                for router in area.routers.values() {
+                    router.get_details();
+                }
+            }
+        });
+        bench("build_graph", &input, runs, || {
+            build_graph(&outputs, &Default::default()).unwrap();
+        });
+
+        // change detection of the gather loop
+        let graph = build_graph(&outputs, &Default::default()).unwrap();
+        let prev_graph = graph.clone();
+        bench("Graph::eq", &input, runs, || {
+            assert!(graph == prev_graph);
+        });
+        let prev_outputs = outputs.clone();
+        bench("outputs eq", &input, runs, || {
+            assert!(outputs == prev_outputs);
+        });
+    }
+}
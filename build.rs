@@ -0,0 +1,24 @@
+use std::process::Command;
+
+/// Provides the git commit which is built as `BIRD2TOPO_GIT_HASH`,
+/// "unknown" outside of a git checkout (unless given via the environment).
+fn main() {
+    println!("cargo:rerun-if-env-changed=BIRD2TOPO_GIT_HASH");
+    // HEAD changes on checkouts, the refs on commits
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let hash = std::env::var("BIRD2TOPO_GIT_HASH")
+        .ok()
+        .or_else(|| {
+            let out = Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()
+                .filter(|o| o.status.success())?;
+            String::from_utf8(out.stdout).ok()
+        })
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=BIRD2TOPO_GIT_HASH={}", hash);
+}
@@ -0,0 +1,11 @@
+#![no_main]
+use bird2topo::parser::{parse_topology, Topology};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(topo) = parse_topology(Topology::new(), data) {
+        // the analysis of whatever got parsed mustn't panic either
+        let _ = topo.to_string();
+        let _ = topo.stats();
+    }
+});
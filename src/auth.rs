@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+/// credentials which clients can use to authenticate themselves
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credential {
+    /// `Authorization: Bearer <token>`
+    Bearer(String),
+    /// `Authorization: Basic <base64(user:password)>`
+    Basic { user: String, password: String },
+}
+
+/// Parses `bearer <token>` or `basic <user>:<password>`.
+impl FromStr for Credential {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Credential, ()> {
+        let s = s.trim();
+        let (kind, rest) = match s.find(char::is_whitespace) {
+            Some(i) => (&s[..i], s[i..].trim_start()),
+            None => return Err(()),
+        };
+        match kind {
+            "bearer" if !rest.is_empty() => Ok(Credential::Bearer(rest.to_string())),
+            "basic" => match rest.find(':') {
+                Some(i) if i > 0 => Ok(Credential::Basic {
+                    user: rest[..i].to_string(),
+                    password: rest[i + 1..].to_string(),
+                }),
+                _ => Err(()),
+            },
+            _ => Err(()),
+        }
+    }
+}
+
+impl Credential {
+    /// the value of the `Authorization` header which carries this credential
+    fn header(&self) -> String {
+        match self {
+            Credential::Bearer(token) => format!("Bearer {}", token),
+            Credential::Basic { user, password } => {
+                format!(
+                    "Basic {}",
+                    base64(format!("{}:{}", user, password).as_bytes())
+                )
+            }
+        }
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut ret = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+/// compares without leaking the position of the first mismatch via timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Auth {
+    /// accepted values of the `Authorization` header
+    accepted: Vec<String>,
+    basic: bool,
+}
+
+impl Auth {
+    pub fn new(creds: &[Credential]) -> Auth {
+        Auth {
+            accepted: creds.iter().map(Credential::header).collect(),
+            basic: creds.iter().any(|i| matches!(i, Credential::Basic { .. })),
+        }
+    }
+
+    /// without credentials, everybody is allowed
+    pub fn is_enabled(&self) -> bool {
+        !self.accepted.is_empty()
+    }
+
+    /// Checks the value of the `Authorization` header of a request.
+    pub fn check(&self, header: Option<&[u8]>) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let header = match header {
+            Some(x) => x,
+            None => return false,
+        };
+        self.accepted
+            .iter()
+            .fold(false, |acc, i| acc | constant_time_eq(i.as_bytes(), header))
+    }
+
+    /// the value of the `WWW-Authenticate` header of a rejection
+    pub fn challenge(&self) -> &'static str {
+        if self.basic {
+            "Basic realm=\"bird2topo\""
+        } else {
+            "Bearer"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(
+            base64(b"Aladdin:open sesame"),
+            "QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+
+    #[test]
+    fn test_parse_credential() {
+        assert_eq!(
+            "bearer abc".parse(),
+            Ok(Credential::Bearer("abc".to_string()))
+        );
+        assert_eq!(
+            "basic admin:se:cret".parse(),
+            Ok(Credential::Basic {
+                user: "admin".to_string(),
+                password: "se:cret".to_string()
+            })
+        );
+        assert_eq!("bearer".parse::<Credential>(), Err(()));
+        assert_eq!("basic admin".parse::<Credential>(), Err(()));
+        assert_eq!("digest x".parse::<Credential>(), Err(()));
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(Auth::default().check(None));
+
+        let auth = Auth::new(&[
+            Credential::Bearer("abc".to_string()),
+            "basic Aladdin:open sesame".parse().unwrap(),
+        ]);
+        assert!(auth.check(Some(b"Bearer abc")));
+        assert!(auth.check(Some(b"Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")));
+        assert!(!auth.check(Some(b"Bearer abd")));
+        assert!(!auth.check(None));
+        assert_eq!(auth.challenge(), "Basic realm=\"bird2topo\"");
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+/// Grows the delay between the attempts of a repeatedly failing operation
+/// (e.g. `birdc` while BIRD is down) exponentially, up to a limit.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// delay as long as nothing fails
+    base: Duration,
+    max: Duration,
+    /// number of consecutive failures
+    failures: u32,
+}
+
+impl Backoff {
+    /// `max` is raised to `base` if necessary
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            base,
+            max: std::cmp::max(base, max),
+            failures: 0,
+        }
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// delay until the next attempt
+    pub fn delay(&self) -> Duration {
+        // more doublings can't stay below any sensible limit anyways
+        let factor = 1u32 << self.failures.min(16);
+        self.base
+            .checked_mul(factor)
+            .map_or(self.max, |x| std::cmp::min(x, self.max))
+    }
+
+    pub fn failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    pub fn success(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progression() {
+        let secs = Duration::from_secs;
+        let mut b = Backoff::new(secs(10), secs(60));
+        assert_eq!(b.delay(), secs(10));
+        let delays: Vec<_> = (0..5)
+            .map(|_| {
+                b.failure();
+                b.delay().as_secs()
+            })
+            .collect();
+        assert_eq!(delays, [20, 40, 60, 60, 60]);
+        assert_eq!(b.failures(), 5);
+        b.success();
+        assert_eq!(b.delay(), secs(10));
+
+        // the limit doesn't undercut the regular delay
+        let mut b = Backoff::new(secs(90), secs(60));
+        b.failure();
+        assert_eq!(b.delay(), secs(90));
+        for _ in 0..100 {
+            b.failure();
+        }
+        assert_eq!(b.delay(), secs(90));
+    }
+}
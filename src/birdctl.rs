@@ -0,0 +1,119 @@
+//! Minimal client for the BIRD control socket (the protocol spoken by birdc).
+//!
+//! Every reply line starts with a four-digit code followed by either a `-`
+//! (more lines with this code follow) or a space (last line of this code);
+//! lines starting with a space continue the previous code.
+//! The reply to a command is terminated by a line with code `0000`,
+//! codes starting with 8 or 9 indicate errors.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BirdCtlError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("connection closed unexpectedly")]
+    UnexpectedEof,
+
+    #[error("invalid reply line: {0}")]
+    InvalidReply(String),
+
+    #[error("BIRD returned error {code}: {msg}")]
+    Bird { code: u16, msg: String },
+}
+
+/// Splits a reply line into the code (if present) and the text.
+fn split_line(line: &str) -> Result<(Option<u16>, &str), BirdCtlError> {
+    if let Some(text) = line.strip_prefix(' ') {
+        return Ok((None, text));
+    }
+    let code = line
+        .get(..4)
+        .and_then(|c| c.parse().ok())
+        .ok_or_else(|| BirdCtlError::InvalidReply(line.to_string()))?;
+    match line.as_bytes().get(4) {
+        Some(b'-') | Some(b' ') => Ok((Some(code), &line[5..])),
+        None => Ok((Some(code), "")),
+        _ => Err(BirdCtlError::InvalidReply(line.to_string())),
+    }
+}
+
+/// Reads one reply, and returns the text of it (without the reply codes).
+/// If `until_end` is false, only the first reply line is read
+/// (used for the greeting).
+fn read_reply(reader: &mut impl BufRead, until_end: bool) -> Result<String, BirdCtlError> {
+    let mut ret = String::new();
+    let mut code = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(BirdCtlError::UnexpectedEof);
+        }
+        let (lcode, text) = split_line(line.trim_end_matches(&['\r', '\n'][..]))?;
+        if let Some(lcode) = lcode {
+            code = lcode;
+        }
+        if code == 0 {
+            break;
+        } else if code >= 8000 {
+            return Err(BirdCtlError::Bird {
+                code,
+                msg: text.to_string(),
+            });
+        }
+        ret.push_str(text);
+        ret.push('\n');
+        if !until_end {
+            break;
+        }
+    }
+    Ok(ret)
+}
+
+/// Runs a command over the control socket at `path`, and returns the output
+/// in the same form birdc would print it (including the greeting banner).
+pub fn query(path: &Path, cmd: &str, timeout: Duration) -> Result<String, BirdCtlError> {
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut ret = read_reply(&mut reader, false)?;
+    writeln!(&stream, "{}", cmd)?;
+    ret.push_str(&read_reply(&mut reader, true)?);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_reply() {
+        let mut inp: &[u8] = b"0001 BIRD v2.0.7 ready.\n\
+            1016-\n \n area 0.0.0.0\n \n \trouter 10.0.0.1\n \t\tdistance 0\n0000 \n";
+        assert_eq!(read_reply(&mut inp, false).unwrap(), "BIRD v2.0.7 ready.\n");
+        assert_eq!(
+            read_reply(&mut inp, true).unwrap(),
+            "\n\narea 0.0.0.0\n\n\trouter 10.0.0.1\n\t\tdistance 0\n"
+        );
+    }
+
+    #[test]
+    fn test_read_reply_error() {
+        let mut inp: &[u8] = b"8003 No such protocol\n";
+        match read_reply(&mut inp, true) {
+            Err(BirdCtlError::Bird { code: 8003, msg }) => assert_eq!(msg, "No such protocol"),
+            x => panic!("unexpected result: {:?}", x),
+        }
+        let mut inp: &[u8] = b"1016-foo\n";
+        assert!(matches!(
+            read_reply(&mut inp, true),
+            Err(BirdCtlError::UnexpectedEof)
+        ));
+    }
+}
@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+/// which build is running, as reported by `--version` and `/version`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// abbreviated hash of the built git commit, or "unknown"
+    pub git_hash: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_hash: env!("BIRD2TOPO_GIT_HASH"),
+};
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bird2topo {} ({})", self.version, self.git_hash)
+    }
+}
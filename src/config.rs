@@ -0,0 +1,560 @@
+use crate::auth::Credential;
+use crate::export::Format;
+use crate::gather::{group, BirdcConfig, Source};
+use crate::parser::ParseOptions;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+static DEFAULT_LISTEN: &str = "127.0.0.1:8942";
+/// lower limit of the intervals, to avoid a hot loop
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("unknown option: {0}")]
+    UnknownOption(String),
+
+    #[error("missing value for option {0}")]
+    MissingValue(String),
+
+    #[error("no OSPF protocols configured (use --proto <name> or --from-file <path>, repeatable)")]
+    NoProtocols,
+
+    #[error("--proto and --from-file can't be combined")]
+    ConflictingSources,
+
+    #[error("{0} requires {1}")]
+    MissingOption(&'static str, &'static str),
+    #[error("{0} and {1} can't be combined")]
+    ConflictingOptions(&'static str, &'static str),
+
+    #[error("invalid value for option {opt}: {value}")]
+    InvalidValue { opt: String, value: String },
+
+    #[error("unknown output format: {0}")]
+    UnknownFormat(String),
+
+    #[error("unable to read auth file {path:?} ({err})")]
+    ReadAuthFile { path: PathBuf, err: String },
+
+    #[error("invalid credential in {0} (expected 'bearer <token>' or 'basic <user>:<password>')")]
+    InvalidCredential(String),
+
+    #[error("invalid listen address '{addr}' ({err})")]
+    InvalidListen {
+        addr: String,
+        err: std::net::AddrParseError,
+    },
+
+    #[error("--tls-cert and --tls-key require a build with the 'tls' feature")]
+    TlsUnsupported,
+}
+
+/// PEM files of the keypair of the WebSocket server
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsFiles {
+    /// certificate, optionally followed by the intermediate certificates
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// address of the WebSocket server
+#[derive(Clone, Debug, PartialEq)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    /// path of a Unix domain socket, given as `unix:<path>`
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listen::Tcp(addr) => addr.fmt(f),
+            Listen::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub listen: Listen,
+    /// serve `wss://` instead of `ws://`
+    pub tls: Option<TlsFiles>,
+    pub source: Source,
+    /// gather the topology once, print it to stdout and exit
+    pub oneshot: bool,
+    /// output format of the oneshot mode
+    pub format: Format,
+    /// indent the JSON output of the oneshot mode and of the HTTP endpoints
+    pub pretty: bool,
+    pub parse_opts: ParseOptions,
+    /// maximum number of simultaneously connected WebSocket clients
+    pub max_clients: usize,
+    /// number of retained revisions of the topology (0 = disabled)
+    pub history: usize,
+    /// time between two gather attempts (as long as nothing changes)
+    pub interval: Duration,
+    /// how long `/healthz` tolerates failing gather attempts
+    pub health_threshold: Duration,
+    /// time between two pings of the connected clients
+    pub ping_interval: Duration,
+    /// how long changes are held back until the topology stabilized (0 = disabled)
+    pub debounce: Duration,
+    /// how long gathered outputs are reused
+    pub cache_ttl: Duration,
+    /// origins (e.g. `https://noc.example.com`) of the web pages which may
+    /// connect, empty = any
+    pub allowed_origins: Vec<String>,
+    /// credentials of which clients must present one, empty = no authentication
+    pub credentials: Vec<Credential>,
+    /// file which maps router ids to friendly names
+    pub labels: Option<PathBuf>,
+    /// use the reverse DNS names of router ids as labels
+    pub resolve_dns: bool,
+    /// merge parallel edges between the same nodes into the cheapest one
+    pub aggregate_links: bool,
+    /// areas which are shown, empty = all
+    pub areas: Vec<String>,
+    /// send an event to the WebSocket clients when a node becomes (un)reachable
+    pub events: bool,
+    /// new names of the node groups, keyed by their built-in names
+    pub group_names: BTreeMap<String, String>,
+    /// id (or label) of the local router, which gets marked
+    pub self_router: Option<String>,
+    /// directory of static files (e.g. the web frontend) to serve
+    pub static_dir: Option<PathBuf>,
+}
+
+/// Parses a duration given in (fractional) seconds, which must be at least `min`
+fn parse_secs(opt: &str, value: String, min: Duration) -> Result<Duration, ConfigError> {
+    value
+        .parse()
+        .ok()
+        .filter(|&secs: &f64| secs.is_finite() && secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .filter(|&x| x >= min)
+        .ok_or_else(|| ConfigError::InvalidValue {
+            opt: opt.to_string(),
+            value,
+        })
+}
+
+/// Reads credentials from a file, one per line; empty lines and
+/// lines starting with `#` are ignored.
+fn read_auth_file(path: PathBuf) -> Result<Vec<Credential>, ConfigError> {
+    let content = std::fs::read_to_string(&path).map_err(|e| ConfigError::ReadAuthFile {
+        path: path.clone(),
+        err: e.to_string(),
+    })?;
+    content
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            line.parse().map_err(|()| {
+                ConfigError::InvalidCredential(format!("{}, line {}", path.display(), n + 1))
+            })
+        })
+        .collect()
+}
+
+/// Parses a TCP address or `unix:<path>`.
+fn parse_listen(addr: String) -> Result<Listen, ConfigError> {
+    use std::str::FromStr;
+    match addr.strip_prefix("unix:") {
+        Some("") => Err(ConfigError::InvalidValue {
+            opt: "--listen".to_string(),
+            value: addr,
+        }),
+        Some(path) => Ok(Listen::Unix(path.into())),
+        None => SocketAddr::from_str(&addr)
+            .map(Listen::Tcp)
+            .map_err(|err| ConfigError::InvalidListen { addr, err }),
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the command line arguments,
+    /// using environment variables as fallback.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        Config::parse(
+            std::env::args().skip(1),
+            std::env::var("BIRD2TOPO_LISTEN").ok(),
+            std::env::var("BIRD2TOPO_AUTH").ok(),
+        )
+    }
+
+    pub fn parse(
+        mut args: impl Iterator<Item = String>,
+        env_listen: Option<String>,
+        env_auth: Option<String>,
+    ) -> Result<Config, ConfigError> {
+        let mut listen = env_listen;
+        let mut protos = Vec::new();
+        let mut birdc = BirdcConfig::default();
+        let mut files = Vec::new();
+        let mut socket: Option<PathBuf> = None;
+        let mut tls_cert: Option<PathBuf> = None;
+        let mut tls_key: Option<PathBuf> = None;
+        // the last given option which only applies to running birdc
+        let mut birdc_opt = None;
+        let mut static_dir: Option<PathBuf> = None;
+        let mut labels: Option<PathBuf> = None;
+        let mut resolve_dns = false;
+        let mut aggregate_links = false;
+        let mut areas = Vec::new();
+        let mut events = false;
+        let mut group_names = BTreeMap::new();
+        let mut self_router = None;
+        let mut oneshot = false;
+        let mut format = Format::Json;
+        let mut pretty = false;
+        let mut parse_opts = ParseOptions::default();
+        let mut max_clients = crate::tokens::DEFAULT_CAPACITY;
+        let mut history = crate::history::DEFAULT_CAPACITY;
+        let mut interval = Duration::from_secs(10);
+        let mut ping_interval = Duration::from_secs(10);
+        let mut health_threshold = None;
+        let mut debounce = Duration::default();
+        let mut cache_ttl = Duration::from_secs(2);
+        let mut allowed_origins = Vec::new();
+        // credentials are never accepted as arguments, because these are visible to all users
+        let mut credentials = match env_auth {
+            Some(x) => vec![x
+                .parse()
+                .map_err(|()| ConfigError::InvalidCredential("BIRD2TOPO_AUTH".to_string()))?],
+            None => Vec::new(),
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| ConfigError::MissingValue(arg.clone()))
+            };
+            match arg.as_str() {
+                "--listen" => listen = Some(value()?),
+                "--proto" => protos.push(value()?),
+                "--birdc" => {
+                    birdc.path = value()?.into();
+                    birdc_opt = Some("--birdc");
+                }
+                "--birdc-prefix" => {
+                    birdc
+                        .prefix_args
+                        .extend(value()?.split_ascii_whitespace().map(str::to_string));
+                    birdc_opt = Some("--birdc-prefix");
+                }
+                "--timeout" => {
+                    birdc.timeout = parse_secs(&arg, value()?, Duration::from_millis(1))?
+                }
+                "--interval" => interval = parse_secs(&arg, value()?, MIN_INTERVAL)?,
+                "--health-threshold" => {
+                    health_threshold = Some(parse_secs(&arg, value()?, MIN_INTERVAL)?)
+                }
+                "--ping-interval" => ping_interval = parse_secs(&arg, value()?, MIN_INTERVAL)?,
+                "--max-clients" => {
+                    let x = value()?;
+                    max_clients = x.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        ConfigError::InvalidValue {
+                            opt: arg.clone(),
+                            value: x,
+                        }
+                    })?;
+                }
+                "--history" => {
+                    let x = value()?;
+                    history = x
+                        .parse()
+                        .ok()
+                        .filter(|&n| n <= crate::history::MAX_CAPACITY)
+                        .ok_or_else(|| ConfigError::InvalidValue {
+                            opt: arg.clone(),
+                            value: x,
+                        })?;
+                }
+                "--debounce" => {
+                    let x = value()?;
+                    debounce = x.parse().map(Duration::from_millis).map_err(|_| {
+                        ConfigError::InvalidValue {
+                            opt: arg.clone(),
+                            value: x,
+                        }
+                    })?;
+                }
+                "--cache-ttl" => cache_ttl = parse_secs(&arg, value()?, Duration::default())?,
+                "--tls-cert" => tls_cert = Some(value()?.into()),
+                "--tls-key" => tls_key = Some(value()?.into()),
+                "--allow-origin" => allowed_origins.push(value()?),
+                "--auth-file" => credentials.extend(read_auth_file(value()?.into())?),
+                "--static-dir" => {
+                    let x = value()?;
+                    if !std::path::Path::new(&x).is_dir() {
+                        return Err(ConfigError::InvalidValue { opt: arg, value: x });
+                    }
+                    static_dir = Some(x.into());
+                }
+                "--labels" => labels = Some(value()?.into()),
+                "--socket" => socket = Some(value()?.into()),
+                "--from-file" => files.push(value()?.into()),
+                "--oneshot" => oneshot = true,
+                "--lenient" => parse_opts.lenient = true,
+                "--distance-policy" => {
+                    let x = value()?;
+                    parse_opts.distance_policy = x
+                        .parse()
+                        .map_err(|()| ConfigError::InvalidValue { opt: arg, value: x })?;
+                }
+                "--resolve-dns" => resolve_dns = true,
+                "--aggregate-links" => aggregate_links = true,
+                "--area" => areas.push(value()?),
+                "--events" => events = true,
+                "--self-router" => self_router = Some(value()?),
+                "--group-name" => {
+                    let x = value()?;
+                    match x.find('=') {
+                        Some(i) if group::ALL.contains(&&x[..i]) && i + 1 < x.len() => {
+                            group_names.insert(x[..i].to_string(), x[i + 1..].to_string());
+                        }
+                        _ => return Err(ConfigError::InvalidValue { opt: arg, value: x }),
+                    }
+                }
+                "--format" => {
+                    let x = value()?;
+                    format = x.parse().map_err(|()| ConfigError::UnknownFormat(x))?;
+                }
+                "--pretty" => pretty = true,
+                _ => return Err(ConfigError::UnknownOption(arg)),
+            }
+        }
+
+        let tls = match (tls_cert, tls_key) {
+            (Some(cert), Some(key)) => Some(TlsFiles { cert, key }),
+            (Some(_), None) => return Err(ConfigError::MissingOption("--tls-cert", "--tls-key")),
+            (None, Some(_)) => return Err(ConfigError::MissingOption("--tls-key", "--tls-cert")),
+            (None, None) => None,
+        };
+        if tls.is_some() && !cfg!(feature = "tls") {
+            return Err(ConfigError::TlsUnsupported);
+        }
+        // options which would be silently ignored otherwise
+        if !files.is_empty() && socket.is_some() {
+            return Err(ConfigError::ConflictingOptions("--socket", "--from-file"));
+        }
+        if let Some(opt) = birdc_opt {
+            if socket.is_some() {
+                return Err(ConfigError::ConflictingOptions(opt, "--socket"));
+            }
+            if !files.is_empty() {
+                return Err(ConfigError::ConflictingOptions(opt, "--from-file"));
+            }
+        }
+        let source = match (protos.is_empty(), files.is_empty()) {
+            (true, true) => return Err(ConfigError::NoProtocols),
+            (false, false) => return Err(ConfigError::ConflictingSources),
+            (false, true) => match socket {
+                Some(path) => Source::Socket {
+                    path,
+                    protos,
+                    timeout: birdc.timeout,
+                },
+                None => Source::Birdc { birdc, protos },
+            },
+            (true, false) => Source::Files(files),
+        };
+
+        Ok(Config {
+            listen: parse_listen(listen.unwrap_or_else(|| DEFAULT_LISTEN.to_string()))?,
+            tls,
+            source,
+            oneshot,
+            format,
+            pretty,
+            parse_opts,
+            max_clients,
+            history,
+            interval,
+            // a single failed attempt shouldn't make the service unhealthy
+            health_threshold: health_threshold.unwrap_or(3 * interval),
+            ping_interval,
+            debounce,
+            cache_ttl,
+            allowed_origins,
+            credentials,
+            labels,
+            resolve_dns,
+            aggregate_links,
+            areas,
+            events,
+            group_names,
+            self_router,
+            static_dir,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        Config::parse(args.iter().map(|i| i.to_string()), None, None)
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse(&["--proto", "ospf4"]).unwrap();
+        assert_eq!(config.listen, Listen::Tcp(DEFAULT_LISTEN.parse().unwrap()));
+        assert!(matches!(config.source, Source::Birdc { ref protos, .. } if protos == &["ospf4"]));
+        assert_eq!(config.interval, Duration::from_secs(10));
+        assert_eq!(config.health_threshold, Duration::from_secs(30));
+
+        let config = Config::parse(
+            vec!["--proto".to_string(), "ospf4".to_string()].into_iter(),
+            Some("[::1]:9000".to_string()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.listen, Listen::Tcp("[::1]:9000".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_listen() {
+        let config = parse(&["--proto", "o", "--listen", "unix:/run/bird2topo.sock"]).unwrap();
+        assert_eq!(config.listen, Listen::Unix("/run/bird2topo.sock".into()));
+        assert_eq!(config.listen.to_string(), "unix:/run/bird2topo.sock");
+        assert!(matches!(
+            parse(&["--proto", "o", "--listen", "unix:"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--proto", "o", "--listen", "localhost"]),
+            Err(ConfigError::InvalidListen { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tls() {
+        assert_eq!(parse(&["--proto", "o"]).unwrap().tls, None);
+        assert!(matches!(
+            parse(&["--proto", "o", "--tls-cert", "cert.pem"]),
+            Err(ConfigError::MissingOption("--tls-cert", "--tls-key"))
+        ));
+        assert!(matches!(
+            parse(&["--proto", "o", "--tls-key", "key.pem"]),
+            Err(ConfigError::MissingOption("--tls-key", "--tls-cert"))
+        ));
+        // the files are loaded at startup
+        let config = parse(&[
+            "--proto",
+            "o",
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ]);
+        if cfg!(feature = "tls") {
+            let tls = TlsFiles {
+                cert: "cert.pem".into(),
+                key: "key.pem".into(),
+            };
+            assert_eq!(config.unwrap().tls, Some(tls));
+        } else {
+            assert!(matches!(config, Err(ConfigError::TlsUnsupported)));
+        }
+    }
+
+    #[test]
+    fn test_sources() {
+        assert!(matches!(parse(&[]), Err(ConfigError::NoProtocols)));
+        assert!(matches!(
+            parse(&["--proto", "ospf4", "--from-file", "x.txt"]),
+            Err(ConfigError::ConflictingSources)
+        ));
+        assert!(matches!(
+            parse(&["--socket", "/run/bird.ctl", "--from-file", "x.txt"]),
+            Err(ConfigError::ConflictingOptions("--socket", "--from-file"))
+        ));
+        assert!(matches!(
+            parse(&[
+                "--socket",
+                "/run/bird.ctl",
+                "--birdc",
+                "/bin/birdc",
+                "--proto",
+                "o"
+            ]),
+            Err(ConfigError::ConflictingOptions("--birdc", "--socket"))
+        ));
+        assert!(matches!(
+            parse(&["--birdc-prefix", "sudo", "--from-file", "x.txt"]),
+            Err(ConfigError::ConflictingOptions(
+                "--birdc-prefix",
+                "--from-file"
+            ))
+        ));
+        let config = parse(&[
+            "--socket",
+            "/run/bird.ctl",
+            "--proto",
+            "o",
+            "--timeout",
+            "2",
+        ]);
+        assert!(matches!(
+            config.unwrap().source,
+            Source::Socket { timeout, .. } if timeout == Duration::from_secs(2)
+        ));
+        assert!(matches!(
+            parse(&["--from-file", "x.txt"]).unwrap().source,
+            Source::Files(files) if files == [PathBuf::from("x.txt")]
+        ));
+    }
+
+    #[test]
+    fn test_intervals() {
+        let config = parse(&["--proto", "o", "--interval", "1", "--ping-interval", "2.5"]);
+        let config = config.unwrap();
+        assert_eq!(config.interval, Duration::from_secs(1));
+        assert_eq!(config.ping_interval, Duration::from_millis(2500));
+        assert_eq!(config.health_threshold, Duration::from_secs(3));
+        for opt in &["--interval", "--ping-interval", "--health-threshold"] {
+            for value in &["0.5", "-1", "NaN", "inf", "ten"] {
+                assert!(
+                    matches!(
+                        parse(&["--proto", "o", opt, value]),
+                        Err(ConfigError::InvalidValue { .. })
+                    ),
+                    "{} {}",
+                    opt,
+                    value
+                );
+            }
+        }
+        assert!(matches!(
+            parse(&["--proto", "o", "--interval"]),
+            Err(ConfigError::MissingValue(_))
+        ));
+        // no minimum
+        let config = parse(&["--proto", "o", "--cache-ttl", "0"]).unwrap();
+        assert_eq!(config.cache_ttl, Duration::default());
+    }
+
+    #[test]
+    fn test_history() {
+        assert_eq!(parse(&["--proto", "o"]).unwrap().history, 64);
+        let config = parse(&["--proto", "o", "--history", "0"]).unwrap();
+        assert_eq!(config.history, 0);
+        let config = parse(&["--proto", "o", "--history", "10000"]).unwrap();
+        assert_eq!(config.history, 10_000);
+        for value in &["10001", "18446744073709551615", "-1"] {
+            assert!(matches!(
+                parse(&["--proto", "o", "--history", value]),
+                Err(ConfigError::InvalidValue { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+/// Coalesces rapid changes of a value (e.g. the topology during a
+/// reconvergence) into a single update.
+///
+/// A changed value is held back until it was observed twice in a row
+/// (i.e. it stabilized), but at most for the configured window.
+#[derive(Debug)]
+pub struct Debouncer<T> {
+    window: Duration,
+    /// the held back value, and when the first change was observed
+    pending: Option<(Instant, T)>,
+}
+
+impl<T: PartialEq> Debouncer<T> {
+    pub fn new(window: Duration) -> Self {
+        Debouncer {
+            window,
+            pending: None,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Feeds a changed value which was observed at `now`,
+    /// returns the value if it should be sent now.
+    pub fn update(&mut self, now: Instant, value: T) -> Option<T> {
+        if self.window == Duration::default() {
+            return Some(value);
+        }
+        match self.pending.take() {
+            // stable, or waited long enough
+            Some((since, old)) if old == value || now >= since + self.window => Some(value),
+            Some((since, _)) => {
+                self.pending = Some((since, value));
+                None
+            }
+            None => {
+                self.pending = Some((now, value));
+                None
+            }
+        }
+    }
+
+    /// Discards the held back value, e.g. because the value flapped back
+    /// to the last sent one.
+    pub fn reset(&mut self) {
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flaps() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut deb = Debouncer::new(Duration::from_millis(1000));
+
+        // three changes within the window, then the value stabilizes
+        let sent: Vec<_> = vec![(0, 1), (100, 2), (200, 3), (300, 3)]
+            .into_iter()
+            .filter_map(|(ms, x)| deb.update(at(ms), x))
+            .collect();
+        assert_eq!(sent, [3]);
+        assert!(!deb.is_pending());
+    }
+
+    #[test]
+    fn test_window_exceeded() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut deb = Debouncer::new(Duration::from_millis(1000));
+
+        assert_eq!(deb.update(at(0), 1), None);
+        assert_eq!(deb.update(at(500), 2), None);
+        // still flapping, but the window is over
+        assert_eq!(deb.update(at(1000), 3), Some(3));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut deb = Debouncer::new(Duration::default());
+        assert_eq!(deb.update(Instant::now(), 1), Some(1));
+        assert!(!deb.is_pending());
+    }
+}
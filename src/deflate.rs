@@ -0,0 +1,337 @@
+//! permessage-deflate (RFC 7692) for the WebSocket connections, which tungstenite
+//! doesn't implement: outgoing messages get compressed via [`compress`], incoming
+//! ones get decompressed by [`Inflater`] before tungstenite reads them
+
+use flate2::{write::DeflateEncoder, Compression, Decompress, FlushDecompress, Status};
+use std::io::{self, Cursor, Read, Write};
+use tungstenite::protocol::frame::coding::{Data, OpCode};
+use tungstenite::protocol::frame::{Frame, FrameHeader};
+use tungstenite::Message;
+
+/// value of the `Sec-WebSocket-Extensions` response header if the extension
+/// got negotiated; each message gets compressed on its own, in both directions
+pub const RESPONSE: &str =
+    "permessage-deflate; server_no_context_takeover; client_no_context_takeover";
+
+/// end of each compressed message, which isn't sent
+const TRAILER: [u8; 4] = [0, 0, 0xff, 0xff];
+
+/// Checks whether one of the offers of the client (the values of its
+/// `Sec-WebSocket-Extensions` headers) is a supported permessage-deflate.
+pub fn negotiate<'a>(headers: impl IntoIterator<Item = &'a str>) -> bool {
+    headers
+        .into_iter()
+        .flat_map(|x| x.split(','))
+        .any(acceptable)
+}
+
+fn acceptable(offer: &str) -> bool {
+    let mut parts = offer.split(';').map(str::trim);
+    if parts.next() != Some("permessage-deflate") {
+        return false;
+    }
+    parts.all(|param| {
+        let (name, value) = match param.split_once('=') {
+            Some((k, v)) => (k.trim(), Some(v.trim().trim_matches('"'))),
+            None => (param, None),
+        };
+        match (name, value) {
+            ("server_no_context_takeover", None) | ("client_no_context_takeover", None) => true,
+            // any window of the client fits into the one of the decompressor
+            ("client_max_window_bits", _) => true,
+            // the compressor always uses the largest window
+            ("server_max_window_bits", Some("15")) => true,
+            _ => false,
+        }
+    })
+}
+
+/// Compresses a text message, the result is sent as it is.
+pub fn compress(text: &str) -> Message {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    // flushing ends the (only) block with the trailer
+    encoder
+        .write_all(text.as_bytes())
+        .and_then(|()| encoder.flush())
+        .expect("unable to compress into memory");
+    let mut data = std::mem::take(encoder.get_mut());
+    if data.ends_with(&TRAILER) {
+        data.truncate(data.len() - TRAILER.len());
+    }
+    let mut frame = Frame::message(data, OpCode::Data(Data::Text), true);
+    frame.header_mut().rsv1 = true;
+    Message::Frame(frame)
+}
+
+fn invalid(msg: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, x) in data.iter_mut().enumerate() {
+        *x ^= mask[i % 4];
+    }
+}
+
+/// Reads the frames of a client from `S` and decompresses the compressed
+/// messages, so that tungstenite only sees uncompressed ones.
+/// Writes are passed through.
+pub struct Inflater<S> {
+    inner: S,
+    /// received data, which doesn't form a complete frame yet
+    input: Vec<u8>,
+    /// processed frames, which weren't read yet
+    output: Vec<u8>,
+    /// read position in `output`
+    pos: usize,
+    /// whether the current (maybe fragmented) message is compressed
+    compressed: bool,
+    decompress: Decompress,
+    /// decompressed length of the current message
+    len: usize,
+    /// upper limit of (decompressed) messages
+    max_len: usize,
+}
+
+impl<S> Inflater<S> {
+    /// `input` is the data which was already read from `inner`.
+    pub fn new(inner: S, input: Vec<u8>, max_len: usize) -> Inflater<S> {
+        Inflater {
+            inner,
+            input,
+            output: Vec::new(),
+            pos: 0,
+            compressed: false,
+            decompress: Decompress::new(false),
+            len: 0,
+            max_len,
+        }
+    }
+
+    /// Moves the next frame from `input` to `output` (decompressed, if necessary),
+    /// returns `false` if there is no complete frame yet.
+    fn process(&mut self) -> io::Result<bool> {
+        let mut cursor = Cursor::new(&self.input);
+        let (mut header, len) = match FrameHeader::parse(&mut cursor).map_err(invalid)? {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        if len > self.max_len as u64 {
+            // tungstenite refuses the frame because of its header
+            std::mem::swap(&mut self.output, &mut self.input);
+            return Ok(true);
+        }
+        let start = cursor.position() as usize;
+        let end = start + len as usize;
+        if self.input.len() < end {
+            return Ok(false);
+        }
+        let first = match header.opcode {
+            OpCode::Data(Data::Continue) => false,
+            OpCode::Data(_) => {
+                self.compressed = header.rsv1;
+                self.len = 0;
+                true
+            }
+            OpCode::Control(_) => false,
+        };
+        if self.compressed && matches!(header.opcode, OpCode::Data(_)) {
+            let mut data = self.input[start..end].to_vec();
+            if let Some(mask) = header.mask {
+                apply_mask(&mut data, mask);
+            }
+            if first {
+                self.decompress.reset(false);
+                // continuations with RSV1 set are left to tungstenite to refuse
+                header.rsv1 = false;
+            }
+            if header.is_final {
+                data.extend_from_slice(&TRAILER);
+                self.compressed = false;
+            }
+            let data = self.inflate(&data)?;
+            // the frame gets masked again
+            Frame::from_payload(header, data.into())
+                .format(&mut self.output)
+                .map_err(invalid)?;
+        } else {
+            self.output.extend_from_slice(&self.input[..end]);
+        }
+        self.input.drain(..end);
+        Ok(true)
+    }
+
+    fn inflate(&mut self, mut data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(data.len() * 4);
+        loop {
+            let before = self.decompress.total_in();
+            let status = self
+                .decompress
+                .decompress_vec(data, &mut out, FlushDecompress::Sync)
+                .map_err(invalid)?;
+            data = &data[(self.decompress.total_in() - before) as usize..];
+            if self.len + out.len() > self.max_len {
+                return Err(invalid("compressed message too long"));
+            }
+            // the output is complete unless it filled the whole buffer
+            if status != Status::Ok || (data.is_empty() && out.len() < out.capacity()) {
+                break;
+            }
+            out.reserve(out.capacity().max(1024));
+        }
+        self.len += out.len();
+        Ok(out)
+    }
+}
+
+impl<S: Read> Read for Inflater<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.output.len() {
+            self.output.clear();
+            self.pos = 0;
+            if self.process()? {
+                continue;
+            }
+            let mut chunk = [0; 4096];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                if self.input.is_empty() {
+                    return Ok(0);
+                }
+                // a truncated frame, which tungstenite reports
+                std::mem::swap(&mut self.output, &mut self.input);
+            }
+            self.input.extend_from_slice(&chunk[..n]);
+        }
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Inflater<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::error::ProtocolError;
+    use tungstenite::protocol::{Role, WebSocket};
+
+    #[test]
+    fn test_negotiate() {
+        assert!(negotiate(vec!["permessage-deflate"]));
+        assert!(negotiate(vec![
+            "permessage-deflate; client_max_window_bits",
+            "x-webkit-deflate-frame"
+        ]));
+        assert!(negotiate(vec![
+            "foo, permessage-deflate; server_max_window_bits=\"15\"; client_no_context_takeover"
+        ]));
+        // falls back to the second offer
+        assert!(negotiate(vec![
+            "permessage-deflate; server_max_window_bits=10, permessage-deflate"
+        ]));
+        assert!(!negotiate(vec![
+            "permessage-deflate; server_max_window_bits=10"
+        ]));
+        assert!(!negotiate(vec!["permessage-deflate; foo"]));
+        assert!(!negotiate(vec!["x-webkit-deflate-frame"]));
+        assert!(!negotiate(Vec::new()));
+    }
+
+    /// Formats a data frame as sent by a client.
+    fn client_frame(data: &[u8], opcode: Data, is_final: bool, rsv1: bool) -> Vec<u8> {
+        let mut frame = Frame::message(data.to_vec(), OpCode::Data(opcode), is_final);
+        frame.header_mut().rsv1 = rsv1;
+        masked(frame)
+    }
+
+    fn masked(mut frame: Frame) -> Vec<u8> {
+        frame.header_mut().mask = Some([1, 2, 3, 4]);
+        let mut out = Vec::new();
+        frame.format(&mut out).unwrap();
+        out
+    }
+
+    fn compressed(text: &str) -> Vec<u8> {
+        match compress(text) {
+            Message::Frame(frame) => {
+                assert!(frame.header().rsv1);
+                frame.payload().to_vec()
+            }
+            x => panic!("unexpected message {:?}", x),
+        }
+    }
+
+    fn read_all(input: Vec<u8>, max_len: usize) -> Vec<tungstenite::Result<Message>> {
+        let inflater = Inflater::new(Cursor::new(Vec::new()), input, max_len);
+        let mut ws = WebSocket::from_raw_socket(inflater, Role::Server, None);
+        let mut ret = Vec::new();
+        loop {
+            match ws.read() {
+                // end of the input
+                Err(tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => {
+                    return ret
+                }
+                x => {
+                    let stop = x.is_err();
+                    ret.push(x);
+                    if stop {
+                        return ret;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_inflate() {
+        let text = r#"{"cmd":"subscribe","protos":["ospf1","ospf2"]}"#;
+        let data = compressed(text);
+        let (a, b) = data.split_at(data.len() / 2);
+        let mut input = client_frame(&data, Data::Text, true, true);
+        // fragmented, interleaved with a ping
+        input.extend(client_frame(a, Data::Text, false, true));
+        input.extend(masked(Frame::ping(b"hi".to_vec())));
+        input.extend(client_frame(b, Data::Continue, true, false));
+        // uncompressed messages are allowed as well
+        input.extend(client_frame(b"plain", Data::Text, true, false));
+
+        let msgs: Vec<_> = read_all(input, 1024)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            msgs,
+            vec![
+                Message::text(text),
+                Message::Ping(b"hi".to_vec().into()),
+                Message::text(text),
+                Message::text("plain"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inflate_limit() {
+        let text = "x".repeat(2000);
+        let input = client_frame(&compressed(&text), Data::Text, true, true);
+        assert_eq!(
+            read_all(input.clone(), 2000).pop().unwrap().unwrap(),
+            Message::text(text)
+        );
+        assert!(read_all(input, 1999).pop().unwrap().is_err());
+        // garbage instead of compressed data
+        let input = client_frame(b"\xff\xff\xff", Data::Text, true, true);
+        assert!(read_all(input, 1024).pop().unwrap().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::gather::{Edge, Graph, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Differences between two versions of a graph. Nodes are identified by
+/// their id, edges don't have an identity, thus a changed edge is
+/// reported as removed and added.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphPatch {
+    /// only present if the BIRD version changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bird_version: Option<Option<String>>,
+    /// added or changed nodes
+    pub nodes: Vec<Node>,
+    /// ids of removed nodes
+    pub removed_nodes: Vec<u64>,
+    pub edges: Vec<Edge>,
+    pub removed_edges: Vec<Edge>,
+}
+
+impl GraphPatch {
+    pub fn is_empty(&self) -> bool {
+        self.bird_version.is_none()
+            && self.nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+}
+
+/// a node which became reachable or unreachable
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub id: u64,
+    pub label: String,
+    pub reachable: bool,
+}
+
+impl Graph {
+    /// Lists the nodes which are part of both versions, but whose reachability
+    /// changed. `unreachable` is the (possibly renamed) group of unreachable nodes.
+    pub fn transitions(&self, new: &Graph, unreachable: &str) -> Vec<Transition> {
+        let old_nodes: BTreeMap<u64, &Node> = self.nodes.iter().map(|i| (i.id, i)).collect();
+        new.nodes
+            .iter()
+            .filter_map(|i| {
+                let was_reachable = old_nodes.get(&i.id)?.group != unreachable;
+                let reachable = i.group != unreachable;
+                if reachable == was_reachable {
+                    return None;
+                }
+                Some(Transition {
+                    id: i.id,
+                    label: i.label.clone(),
+                    reachable,
+                })
+            })
+            .collect()
+    }
+
+    /// Calculates the patch which transforms `self` into `new`.
+    pub fn diff(&self, new: &Graph) -> GraphPatch {
+        let old_nodes: BTreeMap<u64, &Node> = self.nodes.iter().map(|i| (i.id, i)).collect();
+        let new_nodes: BTreeMap<u64, &Node> = new.nodes.iter().map(|i| (i.id, i)).collect();
+        let old_edges: BTreeSet<&Edge> = self.edges.iter().collect();
+        let new_edges: BTreeSet<&Edge> = new.edges.iter().collect();
+
+        GraphPatch {
+            bird_version: if self.bird_version != new.bird_version {
+                Some(new.bird_version.clone())
+            } else {
+                None
+            },
+            nodes: new_nodes
+                .iter()
+                .filter(|(id, node)| old_nodes.get(id) != Some(node))
+                .map(|(_, &node)| node.clone())
+                .collect(),
+            removed_nodes: old_nodes
+                .keys()
+                .filter(|id| !new_nodes.contains_key(id))
+                .copied()
+                .collect(),
+            edges: new_edges
+                .difference(&old_edges)
+                .map(|&i| i.clone())
+                .collect(),
+            removed_edges: old_edges
+                .difference(&new_edges)
+                .map(|&i| i.clone())
+                .collect(),
+        }
+    }
+
+    /// Applies a patch which was calculated via [`Graph::diff`].
+    pub fn apply(&mut self, patch: &GraphPatch) {
+        if let Some(x) = &patch.bird_version {
+            self.bird_version = x.clone();
+        }
+        let mut nodes: BTreeMap<u64, Node> = self.nodes.drain(..).map(|i| (i.id, i)).collect();
+        for i in &patch.removed_nodes {
+            nodes.remove(i);
+        }
+        nodes.extend(patch.nodes.iter().map(|i| (i.id, i.clone())));
+        self.nodes = nodes.into_values().collect();
+
+        self.edges.retain(|i| !patch.removed_edges.contains(i));
+        self.edges.extend(patch.edges.iter().cloned());
+        self.edges.sort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gather::{build_graph, group};
+
+    static BASE: &str = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+\t\tstubnet 10.0.2.0/24 metric 10
+";
+
+    #[test]
+    fn test_metric_change() {
+        let old = build_graph(&[BASE.to_string()], &Default::default()).unwrap();
+        assert!(old.diff(&old).is_empty());
+
+        let changed = BASE.replace(
+            "stubnet 10.0.2.0/24 metric 10",
+            "stubnet 10.0.2.0/24 metric 20",
+        );
+        let new = build_graph(&[changed], &Default::default()).unwrap();
+        let patch = old.diff(&new);
+        assert_eq!(patch.bird_version, None);
+        // only the router which announces the stub network changed
+        let labels: Vec<_> = patch.nodes.iter().map(|i| &i.label[..]).collect();
+        assert_eq!(labels, ["10.0.0.2"]);
+        assert!(patch.removed_nodes.is_empty());
+        assert_eq!(patch.edges.len(), 1);
+        assert_eq!(patch.edges[0].metric, 20);
+        assert_eq!(patch.removed_edges.len(), 1);
+        assert_eq!(patch.removed_edges[0].metric, 10);
+
+        let mut patched = old.clone();
+        patched.apply(&patch);
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let old = build_graph(&[BASE.to_string()], &Default::default()).unwrap();
+        let down = BASE.replace("distance 10", "unreachable");
+        let new = build_graph(&[down], &Default::default()).unwrap();
+        assert!(old.transitions(&old, group::UNREACHABLE).is_empty());
+        let t = old.transitions(&new, group::UNREACHABLE);
+        assert_eq!(t.len(), 1);
+        assert_eq!((&t[0].label[..], t[0].reachable), ("10.0.0.2", false));
+        let t = new.transitions(&old, group::UNREACHABLE);
+        assert_eq!((&t[0].label[..], t[0].reachable), ("10.0.0.2", true));
+    }
+}
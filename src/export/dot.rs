@@ -0,0 +1,137 @@
+use crate::gather::{group, Graph, Node};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Quotes a string as a DOT identifier.
+fn quote(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for i in s.chars() {
+        match i {
+            '"' | '\\' => {
+                ret.push('\\');
+                ret.push(i);
+            }
+            '\n' => ret.push_str("\\n"),
+            _ => ret.push(i),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn group_attrs(group: &str) -> &'static str {
+    match group {
+        group::ROUTER => "shape=ellipse, color=blue",
+        group::ABR => "shape=doublecircle, color=blue",
+        group::ASBR => "shape=doubleoctagon, color=purple",
+        group::NETWORK => "shape=box, color=darkgreen",
+        group::STUBNET => "shape=box, color=gray",
+        group::UNREACHABLE => "shape=ellipse, color=red, style=dashed",
+        _ => "shape=ellipse",
+    }
+}
+
+fn write_node(ret: &mut String, indent: &str, node: &Node) {
+    writeln!(
+        ret,
+        "{}{} [label={}, {}];",
+        indent,
+        node.id,
+        quote(&node.label),
+        group_attrs(&node.group)
+    )
+    .unwrap();
+}
+
+impl Graph {
+    /// Renders the graph in the Graphviz DOT format,
+    /// the nodes of each area are grouped into a cluster.
+    pub fn to_dot(&self) -> String {
+        let mut areas: BTreeMap<&str, Vec<&Node>> = BTreeMap::new();
+        let mut unassigned = Vec::new();
+        for i in &self.nodes {
+            match &i.area {
+                Some(area) => areas.entry(area).or_default().push(i),
+                None => unassigned.push(i),
+            }
+        }
+
+        let mut ret = String::new();
+        ret.push_str("digraph topology {\n");
+        for (area, nodes) in areas {
+            writeln!(ret, "  subgraph {} {{", quote(&format!("cluster_{}", area))).unwrap();
+            writeln!(ret, "    label={};", quote(&format!("area {}", area))).unwrap();
+            for i in nodes {
+                write_node(&mut ret, "    ", i);
+            }
+            ret.push_str("  }\n");
+        }
+        for i in unassigned {
+            write_node(&mut ret, "  ", i);
+        }
+        for i in &self.edges {
+            writeln!(
+                ret,
+                "  {} -> {} [label={}, dir=none{}{}];",
+                i.from,
+                i.to,
+                quote(&i.metric.to_string()),
+                if i.asymmetric { ", color=orange" } else { "" },
+                if i.virtual_link { ", style=dashed" } else { "" }
+            )
+            .unwrap();
+        }
+        ret.push_str("}\n");
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gather::Edge;
+
+    #[test]
+    fn test_to_dot() {
+        let node = |id, label: &str, group: &str, area: Option<&str>| Node {
+            id,
+            label: label.to_string(),
+            group: group.to_string(),
+            area: area.map(str::to_string),
+            details: Default::default(),
+        };
+        let graph = Graph {
+            bird_version: None,
+            schema_version: crate::gather::SCHEMA_VERSION,
+            edges: vec![Edge {
+                from: 1,
+                to: 2,
+                length: 11,
+                metric: 1000,
+                external: false,
+                area: "0.0.0.0".to_string(),
+                asymmetric: false,
+                virtual_link: false,
+            }],
+            nodes: vec![
+                node(1, "10.0.0.1", "ytrizja", Some("0.0.0.0")),
+                node(2, "10.0.0.0/24", "network", Some("0.0.0.0")),
+                node(3, "fe80::\"1\"", "unreachable", None),
+            ],
+        };
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph topology {
+  subgraph "cluster_0.0.0.0" {
+    label="area 0.0.0.0";
+    1 [label="10.0.0.1", shape=ellipse, color=blue];
+    2 [label="10.0.0.0/24", shape=box, color=darkgreen];
+  }
+  3 [label="fe80::\"1\"", shape=ellipse, color=red, style=dashed];
+  1 -> 2 [label="1000", dir=none];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::gather::Graph;
+use std::fmt::Write;
+
+/// Escapes a string for use in XML text and attribute values.
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for i in s.chars() {
+        match i {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            _ => ret.push(i),
+        }
+    }
+    ret
+}
+
+impl Graph {
+    /// Renders the graph in the GraphML format (e.g. for yEd or Gephi).
+    pub fn to_graphml(&self) -> String {
+        let mut ret = String::new();
+        ret.push_str(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"group\" for=\"node\" attr.name=\"group\" attr.type=\"string\"/>\n",
+            "  <key id=\"area\" for=\"node\" attr.name=\"area\" attr.type=\"string\"/>\n",
+            "  <key id=\"distance\" for=\"node\" attr.name=\"distance\" attr.type=\"int\"/>\n",
+            "  <key id=\"length\" for=\"edge\" attr.name=\"length\" attr.type=\"int\"/>\n",
+            "  <key id=\"metric\" for=\"edge\" attr.name=\"metric\" attr.type=\"int\"/>\n",
+            "  <graph id=\"topology\" edgedefault=\"undirected\">\n",
+        ));
+        for i in &self.nodes {
+            writeln!(ret, "    <node id=\"n{}\">", i.id).unwrap();
+            writeln!(ret, "      <data key=\"label\">{}</data>", escape(&i.label)).unwrap();
+            writeln!(ret, "      <data key=\"group\">{}</data>", escape(&i.group)).unwrap();
+            if let Some(area) = &i.area {
+                writeln!(ret, "      <data key=\"area\">{}</data>", escape(area)).unwrap();
+            }
+            if let Some(distance) = i.details.get("distance").and_then(|d| d.as_u64()) {
+                writeln!(ret, "      <data key=\"distance\">{}</data>", distance).unwrap();
+            }
+            ret.push_str("    </node>\n");
+        }
+        for i in &self.edges {
+            writeln!(
+                ret,
+                "    <edge source=\"n{}\" target=\"n{}\">",
+                i.from, i.to
+            )
+            .unwrap();
+            writeln!(ret, "      <data key=\"length\">{}</data>", i.length).unwrap();
+            writeln!(ret, "      <data key=\"metric\">{}</data>", i.metric).unwrap();
+            ret.push_str("    </edge>\n");
+        }
+        ret.push_str("  </graph>\n</graphml>\n");
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gather::{Edge, Node};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("fe80::1/64"), "fe80::1/64");
+        assert_eq!(escape("<a & 'b'>\""), "&lt;a &amp; &apos;b&apos;&gt;&quot;");
+    }
+
+    #[test]
+    fn test_to_graphml() {
+        let mut details = serde_json::Map::new();
+        details.insert("distance".to_string(), 10.into());
+        let graph = Graph {
+            bird_version: None,
+            schema_version: crate::gather::SCHEMA_VERSION,
+            edges: vec![Edge {
+                from: 1,
+                to: 2,
+                length: 11,
+                metric: 1000,
+                external: false,
+                area: "0.0.0.0".to_string(),
+                asymmetric: false,
+                virtual_link: false,
+            }],
+            nodes: vec![
+                Node {
+                    id: 1,
+                    label: "10.0.0.1".to_string(),
+                    group: "ytrizja".to_string(),
+                    area: Some("0.0.0.0".to_string()),
+                    details,
+                },
+                Node {
+                    id: 2,
+                    label: "2001:db8::/64".to_string(),
+                    group: "network".to_string(),
+                    area: None,
+                    details: Default::default(),
+                },
+            ],
+        };
+        let out = graph.to_graphml();
+        assert!(out.contains(concat!(
+            "    <node id=\"n1\">\n",
+            "      <data key=\"label\">10.0.0.1</data>\n",
+            "      <data key=\"group\">ytrizja</data>\n",
+            "      <data key=\"area\">0.0.0.0</data>\n",
+            "      <data key=\"distance\">10</data>\n",
+            "    </node>\n",
+            "    <node id=\"n2\">\n",
+            "      <data key=\"label\">2001:db8::/64</data>\n",
+            "      <data key=\"group\">network</data>\n",
+            "    </node>\n",
+            "    <edge source=\"n1\" target=\"n2\">\n",
+            "      <data key=\"length\">11</data>\n",
+            "      <data key=\"metric\">1000</data>\n",
+            "    </edge>\n",
+            "  </graph>\n</graphml>\n",
+        )));
+    }
+}
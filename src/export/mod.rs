@@ -0,0 +1,63 @@
+use crate::gather::Graph;
+
+mod dot;
+mod graphml;
+mod msgpack;
+mod netjson;
+
+pub use netjson::{NetJsonLink, NetJsonNode, NetworkGraph};
+
+/// output formats supported for a gathered topology
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Dot,
+    GraphMl,
+    NetJson,
+    MsgPack,
+}
+
+impl std::str::FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(match s {
+            "json" => Format::Json,
+            "dot" => Format::Dot,
+            "graphml" => Format::GraphMl,
+            "netjson" => Format::NetJson,
+            "msgpack" => Format::MsgPack,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Serializes `x` as JSON, indented if `pretty`.
+pub fn to_json<T: serde::Serialize>(x: &T, pretty: bool) -> String {
+    if pretty {
+        serde_json::to_string_pretty(x)
+    } else {
+        serde_json::to_string(x)
+    }
+    .expect("unable to serialize data")
+}
+
+impl Format {
+    /// whether the output isn't text (and thus doesn't end with a newline)
+    pub fn is_binary(self) -> bool {
+        self == Format::MsgPack
+    }
+}
+
+impl Graph {
+    /// `pretty` only affects the JSON based formats
+    pub fn render(&self, format: Format, pretty: bool) -> Vec<u8> {
+        match format {
+            Format::Json => to_json(self, pretty).into_bytes(),
+            Format::Dot => self.to_dot().into_bytes(),
+            Format::GraphMl => self.to_graphml().into_bytes(),
+            Format::NetJson => to_json(&self.to_netjson(), pretty).into_bytes(),
+            Format::MsgPack => self.to_msgpack(),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::gather::Graph;
+use serde_json::Value;
+
+/// Appends the MessagePack encoding of `val` to `out`,
+/// see <https://github.com/msgpack/msgpack/blob/master/spec.md>
+fn encode(out: &mut Vec<u8>, val: &Value) {
+    match val {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(n) => {
+            if let Some(x) = n.as_u64() {
+                encode_uint(out, x);
+            } else if let Some(x) = n.as_i64() {
+                encode_int(out, x);
+            } else {
+                out.push(0xcb);
+                out.extend_from_slice(&n.as_f64().unwrap_or(f64::NAN).to_be_bytes());
+            }
+        }
+        Value::String(s) => encode_str(out, s),
+        Value::Array(a) => {
+            // there is no `array 8`, thus 0xdc is repeated
+            encode_len(out, a.len(), 0x90, 16, [0xdc, 0xdc, 0xdd]);
+            for i in a {
+                encode(out, i);
+            }
+        }
+        Value::Object(o) => {
+            encode_len(out, o.len(), 0x80, 16, [0xde, 0xde, 0xdf]);
+            for (k, v) in o {
+                encode_str(out, k);
+                encode(out, v);
+            }
+        }
+    }
+}
+
+fn encode_str(out: &mut Vec<u8>, s: &str) {
+    encode_len(out, s.len(), 0xa0, 32, [0xd9, 0xda, 0xdb]);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn encode_uint(out: &mut Vec<u8>, x: u64) {
+    if x < 0x80 {
+        out.push(x as u8);
+    } else if x <= u8::MAX.into() {
+        out.extend_from_slice(&[0xcc, x as u8]);
+    } else if x <= u16::MAX.into() {
+        out.push(0xcd);
+        out.extend_from_slice(&(x as u16).to_be_bytes());
+    } else if x <= u32::MAX.into() {
+        out.push(0xce);
+        out.extend_from_slice(&(x as u32).to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend_from_slice(&x.to_be_bytes());
+    }
+}
+
+/// only used for negative numbers
+fn encode_int(out: &mut Vec<u8>, x: i64) {
+    if x >= -32 {
+        out.push(x as u8);
+    } else if x >= i8::MIN.into() {
+        out.extend_from_slice(&[0xd0, x as u8]);
+    } else if x >= i16::MIN.into() {
+        out.push(0xd1);
+        out.extend_from_slice(&(x as i16).to_be_bytes());
+    } else if x >= i32::MIN.into() {
+        out.push(0xd2);
+        out.extend_from_slice(&(x as i32).to_be_bytes());
+    } else {
+        out.push(0xd3);
+        out.extend_from_slice(&x.to_be_bytes());
+    }
+}
+
+/// Encodes the header of a string, array or map, which either fits into
+/// the `fix` variant (below `fixmax`), or uses one of the 8/16/32 bit variants.
+fn encode_len(out: &mut Vec<u8>, len: usize, fix: u8, fixmax: usize, tags: [u8; 3]) {
+    if len < fixmax {
+        out.push(fix | len as u8);
+    } else if len <= u8::MAX.into() && tags[0] != tags[1] {
+        out.extend_from_slice(&[tags[0], len as u8]);
+    } else if len <= u16::MAX.into() {
+        out.push(tags[1]);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(tags[2]);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+impl Graph {
+    /// Encodes the graph as MessagePack, with the same structure as the JSON output.
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        let val = serde_json::to_value(self).expect("unable to serialize data");
+        let mut out = Vec::new();
+        encode(&mut out, &val);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn enc(val: Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode(&mut out, &val);
+        out
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(enc(json!(null)), [0xc0]);
+        assert_eq!(enc(json!([false, true])), [0x92, 0xc2, 0xc3]);
+        assert_eq!(enc(json!(127)), [0x7f]);
+        assert_eq!(enc(json!(200)), [0xcc, 200]);
+        assert_eq!(enc(json!(1000)), [0xcd, 0x03, 0xe8]);
+        assert_eq!(
+            enc(json!(u64::MAX)),
+            [0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(enc(json!(-1)), [0xff]);
+        assert_eq!(enc(json!(-33)), [0xd0, 0xdf]);
+        assert_eq!(enc(json!(-1000)), [0xd1, 0xfc, 0x18]);
+        assert_eq!(enc(json!(1.5)), [0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_containers() {
+        assert_eq!(
+            enc(json!({"a": "bc"})),
+            [0x81, 0xa1, b'a', 0xa2, b'b', b'c']
+        );
+        let s = "x".repeat(40);
+        assert_eq!(enc(json!(s))[..2], [0xd9, 40]);
+        assert_eq!(enc(json!(vec![0; 20]))[..3], [0xdc, 0, 20]);
+        let map: serde_json::Map<_, _> = (0..300).map(|i| (i.to_string(), json!(i))).collect();
+        assert_eq!(enc(Value::Object(map))[..3], [0xde, 0x01, 0x2c]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::gather::Graph;
+use serde::{Deserialize, Serialize};
+use serde_json::{map::Map, Value};
+
+/// NetJSON `NetworkGraph` object, see <https://netjson.org/rfc.html>
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkGraph {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub protocol: String,
+    pub version: Option<String>,
+    pub metric: Option<String>,
+    pub nodes: Vec<NetJsonNode>,
+    pub links: Vec<NetJsonLink>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetJsonNode {
+    pub id: String,
+    pub label: String,
+    pub properties: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetJsonLink {
+    pub source: String,
+    pub target: String,
+    pub cost: u32,
+    pub properties: Map<String, Value>,
+}
+
+impl Graph {
+    /// Converts the graph into a NetJSON `NetworkGraph`.
+    pub fn to_netjson(&self) -> NetworkGraph {
+        NetworkGraph {
+            typ: "NetworkGraph".to_string(),
+            protocol: "OSPF".to_string(),
+            version: None,
+            metric: None,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|i| {
+                    let mut properties = Map::new();
+                    properties.insert("group".to_string(), Value::String(i.group.clone()));
+                    if let Some(area) = &i.area {
+                        properties.insert("area".to_string(), Value::String(area.clone()));
+                    }
+                    NetJsonNode {
+                        id: i.id.to_string(),
+                        label: i.label.clone(),
+                        properties,
+                    }
+                })
+                .collect(),
+            links: self
+                .edges
+                .iter()
+                .map(|i| {
+                    let mut properties = Map::new();
+                    properties.insert("area".to_string(), Value::String(i.area.clone()));
+                    if i.external {
+                        properties.insert("external".to_string(), Value::Bool(true));
+                    }
+                    if i.virtual_link {
+                        properties.insert("virtual_link".to_string(), Value::Bool(true));
+                    }
+                    NetJsonLink {
+                        source: i.from.to_string(),
+                        target: i.to.to_string(),
+                        cost: i.metric,
+                        properties,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gather::{Edge, Graph, Node};
+
+    #[test]
+    fn test_to_netjson() {
+        let graph = Graph {
+            bird_version: None,
+            schema_version: crate::gather::SCHEMA_VERSION,
+            edges: vec![Edge {
+                from: 1,
+                to: 2,
+                length: 11,
+                metric: 1000,
+                external: false,
+                area: "0.0.0.0".to_string(),
+                asymmetric: false,
+                virtual_link: false,
+            }],
+            nodes: vec![
+                Node {
+                    id: 1,
+                    label: "10.0.0.1".to_string(),
+                    group: "ytrizja".to_string(),
+                    area: Some("0.0.0.0".to_string()),
+                    details: Default::default(),
+                },
+                Node {
+                    id: 2,
+                    label: "10.0.0.0/24".to_string(),
+                    group: "network".to_string(),
+                    area: None,
+                    details: Default::default(),
+                },
+            ],
+        };
+        assert_eq!(
+            serde_json::to_value(graph.to_netjson()).unwrap(),
+            serde_json::json!({
+                "type": "NetworkGraph",
+                "protocol": "OSPF",
+                "version": null,
+                "metric": null,
+                "nodes": [
+                    {
+                        "id": "1",
+                        "label": "10.0.0.1",
+                        "properties": { "group": "ytrizja", "area": "0.0.0.0" }
+                    },
+                    {
+                        "id": "2",
+                        "label": "10.0.0.0/24",
+                        "properties": { "group": "network" }
+                    }
+                ],
+                "links": [
+                    {
+                        "source": "1",
+                        "target": "2",
+                        "cost": 1000,
+                        "properties": { "area": "0.0.0.0" }
+                    }
+                ]
+            })
+        );
+    }
+}
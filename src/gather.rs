@@ -1,140 +1,1400 @@
-use serde::Serialize;
+use crate::parser::{EntryType, Metric, ParseOptions};
+use serde::{Deserialize, Serialize};
 use serde_json::{map::Map, Value};
-use std::collections::HashMap;
-use tracing::error;
-
-#[derive(Clone, Serialize)]
-struct Node {
-    id: u64,
-    label: String,
-    group: String,
-    details: Map<String, Value>,
-}
-
-#[derive(Serialize, PartialOrd, PartialEq, Ord, Eq)]
-struct Edge {
-    from: u64,
-    to: u64,
-    length: u16,
-}
-
-pub fn gather(protos: &[&str]) -> Option<String> {
-    let mut tmp = Vec::new();
-    for i in protos.iter().copied() {
-        let outp = match std::process::Command::new("birdc")
-            .args(&["show", "ospf", "state", "all", i])
-            .output()
-        {
-            Ok(outp) => outp,
-            Err(x) => {
-                error!("gather: run birdc[{}] failed: {:?}", i, x);
-                continue;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+#[derive(Clone, Debug)]
+pub struct BirdcConfig {
+    /// path to the birdc executable
+    pub path: PathBuf,
+    /// wrapper command (with arguments) which is prepended to the birdc
+    /// invocation, e.g. `ip netns exec bird`
+    pub prefix_args: Vec<String>,
+    /// birdc gets killed if it doesn't finish in time
+    pub timeout: Duration,
+}
+
+impl Default for BirdcConfig {
+    fn default() -> Self {
+        BirdcConfig {
+            path: "birdc".into(),
+            prefix_args: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl BirdcConfig {
+    fn command(&self, proto: &str) -> Command {
+        let mut cmd = match self.prefix_args.split_first() {
+            Some((wrapper, wrapper_args)) => {
+                let mut cmd = Command::new(wrapper);
+                cmd.args(wrapper_args).arg(&self.path);
+                cmd
             }
+            None => Command::new(&self.path),
         };
-        if !outp.status.success() {
-            error!(
-                "gather: run birdc[{}] failed:\n{}",
-                i,
-                String::from_utf8_lossy(&outp.stderr[..])
+        cmd.args(&["show", "ospf", "state", "all", proto]);
+        cmd
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: u64,
+    pub label: String,
+    pub group: String,
+    /// the first area in which the node was encountered
+    pub area: Option<String>,
+    pub details: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+pub struct Edge {
+    pub from: u64,
+    pub to: u64,
+    /// display length (bucketed link cost, see [`display_length`]), used for the layout
+    pub length: u32,
+    /// OSPF link cost
+    pub metric: u32,
+    /// the cost is an external (type 2) metric, which is always
+    /// considered worse than any internal one
+    #[serde(default)]
+    pub external: bool,
+    pub area: String,
+    /// the link costs differ depending on the direction
+    #[serde(default)]
+    pub asymmetric: bool,
+    /// OSPF virtual link (through a transit area)
+    #[serde(default)]
+    pub virtual_link: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Graph {
+    // field order matches the previously used (key-sorted) JSON map
+    /// BIRD version which produced the data (if known)
+    pub bird_version: Option<String>,
+    pub edges: Vec<Edge>,
+    pub nodes: Vec<Node>,
+    /// version of the serialized shape (see `schema/graph.schema.json`)
+    pub schema_version: u32,
+}
+
+/// Bumped on every change of the serialized shape of [`Graph`] (e.g. new fields).
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// built-in names of the node groups
+pub mod group {
+    /// routers (within a single area)
+    pub const ROUTER: &str = "ytrizja";
+    /// area border routers
+    pub const ABR: &str = "abr";
+    /// AS boundary routers
+    pub const ASBR: &str = "asbr";
+    /// transit networks
+    pub const NETWORK: &str = "network";
+    pub const STUBNET: &str = "stubnet";
+    pub const UNREACHABLE: &str = "unreachable";
+
+    pub const ALL: [&str; 6] = [ROUTER, ABR, ASBR, NETWORK, STUBNET, UNREACHABLE];
+}
+
+/// offset which gets added to external metrics when calculating the display length,
+/// so that external links are laid out longer than internal ones
+pub const EXTERNAL_METRIC_OFFSET: u32 = 1000;
+
+/// Calculates the display length of a link with the given cost.
+///
+/// Costs below 100000 are bucketed linearly (one step per 100, i.e. 1..=1000),
+/// larger costs (e.g. type 2 external metrics) are scaled logarithmically
+/// (100 per doubling), so that they stay distinguishable without dominating
+/// the layout.
+pub fn display_length(cost: u32) -> u32 {
+    const LINEAR_LIMIT: u32 = 100_000;
+    if cost < LINEAR_LIMIT {
+        cost / 100 + 1
+    } else {
+        let doublings = (f64::from(cost) / f64::from(LINEAR_LIMIT)).log2();
+        LINEAR_LIMIT / 100 + (100.0 * doublings) as u32
+    }
+}
+
+/// Hasher for the maps keyed by node ids (FxHash): the ids are already
+/// hashes, thus the DoS resistance of the default hasher isn't needed.
+#[derive(Clone, Copy, Default)]
+pub struct IdHasher(u64);
+
+impl IdHasher {
+    fn add(&mut self, x: u64) {
+        self.0 = (self.0.rotate_left(5) ^ x).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for i in &mut chunks {
+            let mut x = [0u8; 8];
+            x.copy_from_slice(i);
+            self.add(u64::from_le_bytes(x));
+        }
+        for &i in chunks.remainder() {
+            self.add(u64::from(i));
+        }
+    }
+
+    fn write_u64(&mut self, x: u64) {
+        self.add(x);
+    }
+
+    fn write_usize(&mut self, x: usize) {
+        self.add(x as u64);
+    }
+}
+
+type IdMap<K, V> = HashMap<K, V, BuildHasherDefault<IdHasher>>;
+type IdSet<K> = HashSet<K, BuildHasherDefault<IdHasher>>;
+
+/// (label, is reachable, details, first area)
+type NodeInfo<'a> = (&'a str, bool, Map<String, Value>, Option<&'a str>);
+
+/// Merges the details of a node which were collected in another area into
+/// the already present details. List entries are combined, scalar entries
+/// of the first area take precedence.
+fn merge_details(dst: &mut Map<String, Value>, src: Map<String, Value>) {
+    use serde_json::map::Entry;
+    for (k, v) in src {
+        match dst.entry(k) {
+            Entry::Vacant(e) => {
+                e.insert(v);
+            }
+            Entry::Occupied(mut e) => {
+                if let (Value::Array(a), Value::Array(b)) = (e.get_mut(), v) {
+                    a.extend(b);
+                }
+            }
+        }
+    }
+}
+
+impl Graph {
+    /// Merges parallel edges (between the same nodes, in the same area) into
+    /// the cheapest one; internal costs are preferred over external ones.
+    pub fn aggregate_parallel_edges(&mut self) {
+        let mut best: BTreeMap<(u64, u64, String), Edge> = BTreeMap::new();
+        for i in self.edges.drain(..) {
+            use std::collections::btree_map::Entry;
+            match best.entry((i.from, i.to, i.area.clone())) {
+                Entry::Vacant(e) => {
+                    e.insert(i);
+                }
+                Entry::Occupied(mut e) => {
+                    let e = e.get_mut();
+                    let asymmetric = e.asymmetric || i.asymmetric;
+                    if (i.external, i.metric) < (e.external, e.metric) {
+                        *e = i;
+                    }
+                    e.asymmetric = asymmetric;
+                }
+            }
+        }
+        self.edges = best.into_values().collect();
+        self.edges.sort();
+        self.update_degrees();
+    }
+
+    /// Checks that all edges connect existing nodes. Dangling edges are an
+    /// error if `strict` is set, otherwise they get logged and dropped.
+    pub fn check_edges(&mut self, strict: bool) -> Result<(), GatherError> {
+        let ids: IdSet<u64> = self.nodes.iter().map(|i| i.id).collect();
+        let mut err = None;
+        self.edges.retain(|i| {
+            if ids.contains(&i.from) && ids.contains(&i.to) {
+                return true;
+            }
+            warn!(
+                "gather: edge from {} to {} (area {}) references an unknown node",
+                i.from, i.to, i.area
             );
-            continue;
+            err.get_or_insert(GatherError::DanglingEdge {
+                from: i.from,
+                to: i.to,
+            });
+            false
+        });
+        match err {
+            Some(x) if strict => Err(x),
+            _ => Ok(()),
+        }
+    }
+
+    /// Restricts the graph to the given areas: edges of other areas are
+    /// dropped, as well as the nodes which only appear in other areas.
+    pub fn retain_areas(&mut self, areas: &[String]) {
+        let node_areas = |node: &Node| -> Vec<String> {
+            match node.details.get("areas") {
+                Some(Value::Array(x)) => x
+                    .iter()
+                    .filter_map(|i| i.as_str().map(str::to_string))
+                    .collect(),
+                _ => node.area.iter().cloned().collect(),
+            }
+        };
+        for i in areas {
+            if !self.nodes.iter().any(|node| node_areas(node).contains(i)) {
+                warn!("gather: area {} doesn't exist", i);
+            }
+        }
+        self.edges.retain(|i| areas.contains(&i.area));
+        self.nodes.retain_mut(|node| {
+            let kept = node_areas(node).into_iter().find(|i| areas.contains(i));
+            if kept.is_some() && node.area.as_ref().map(|i| areas.contains(i)) != Some(true) {
+                // the first area of the node was dropped
+                node.area = kept.clone();
+            }
+            kept.is_some()
+        });
+        self.update_degrees();
+    }
+
+    /// Sets `details["degree"]` of every node to its number of edges.
+    pub fn update_degrees(&mut self) {
+        let mut degrees: IdMap<u64, u64> =
+            IdMap::with_capacity_and_hasher(self.nodes.len(), Default::default());
+        for i in &self.edges {
+            *degrees.entry(i.from).or_default() += 1;
+            *degrees.entry(i.to).or_default() += 1;
+        }
+        for node in self.nodes.iter_mut() {
+            let degree = degrees.get(&node.id).copied().unwrap_or(0);
+            node.details
+                .insert("degree".to_string(), Value::Number(degree.into()));
+        }
+    }
+
+    /// Renames the groups of the nodes (e.g. `ytrizja` to `router`),
+    /// groups without a new name are kept.
+    pub fn rename_groups(&mut self, names: &BTreeMap<String, String>) {
+        for node in self.nodes.iter_mut() {
+            if let Some(name) = names.get(&node.group) {
+                node.group = name.clone();
+            }
+        }
+    }
+}
+
+/// where the `birdc show ospf state all` outputs come from
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// run birdc for each of the given protocols
+    Birdc {
+        birdc: BirdcConfig,
+        protos: Vec<String>,
+    },
+    /// query the BIRD control socket for each of the given protocols
+    Socket {
+        path: PathBuf,
+        protos: Vec<String>,
+        timeout: Duration,
+    },
+    /// read previously saved birdc outputs
+    Files(Vec<PathBuf>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GatherError {
+    #[error("unable to run birdc[{proto}]: {err}")]
+    BirdcSpawn { proto: String, err: std::io::Error },
+
+    #[error("birdc[{proto}] failed: {stderr}")]
+    BirdcExit { proto: String, stderr: String },
+
+    #[error("birdc[{proto}] didn't finish within {timeout:?}")]
+    BirdcTimeout { proto: String, timeout: Duration },
+
+    #[error("birdc[{0}] returned non-UTF-8 output")]
+    NonUtf8(String),
+
+    #[error("querying bird[{proto}] failed: {err}")]
+    Socket {
+        proto: String,
+        err: crate::birdctl::BirdCtlError,
+    },
+
+    #[error("reading {} failed: {}", .path.display(), .err)]
+    ReadFile { path: PathBuf, err: std::io::Error },
+
+    #[error("parsing birdc output failed: {0}")]
+    Parse(String),
+
+    #[error("the topology is empty")]
+    Empty,
+
+    #[error("edge from {from} to {to} references an unknown node")]
+    DanglingEdge { from: u64, to: u64 },
+}
+
+/// Reads the given pipe to the end in a background thread,
+/// so that the child process doesn't block on a full pipe.
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+fn run_birdc(birdc: &BirdcConfig, proto: &str) -> Result<String, GatherError> {
+    let mut child = birdc
+        .command(proto)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| GatherError::BirdcSpawn {
+            proto: proto.to_string(),
+            err,
+        })?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + birdc.timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(GatherError::BirdcTimeout {
+                    proto: proto.to_string(),
+                    timeout: birdc.timeout,
+                });
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(err) => {
+                return Err(GatherError::BirdcSpawn {
+                    proto: proto.to_string(),
+                    err,
+                })
+            }
+        }
+    };
+
+    let stdout = stdout.join().expect("birdc stdout reader panicked");
+    let stderr = stderr.join().expect("birdc stderr reader panicked");
+    if !status.success() {
+        return Err(GatherError::BirdcExit {
+            proto: proto.to_string(),
+            stderr: String::from_utf8_lossy(&stderr[..]).into_owned(),
+        });
+    }
+    String::from_utf8(stdout).map_err(|_| GatherError::NonUtf8(proto.to_string()))
+}
+
+fn query_socket(path: &Path, proto: &str, timeout: Duration) -> Result<String, GatherError> {
+    let cmd = format!("show ospf state all {}", proto);
+    crate::birdctl::query(path, &cmd, timeout).map_err(|err| GatherError::Socket {
+        proto: proto.to_string(),
+        err,
+    })
+}
+
+/// Fetches the outputs for all protocols concurrently,
+/// the results are kept in the order of `protos`.
+fn fetch_parallel<F>(protos: &[String], f: F) -> Vec<Result<String, GatherError>>
+where
+    F: Fn(&str) -> Result<String, GatherError> + Sync,
+{
+    let f = &f;
+    std::thread::scope(|s| {
+        let handles: Vec<_> = protos.iter().map(|i| s.spawn(move || f(i))).collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("gather: fetch thread panicked"))
+            .collect()
+    })
+}
+
+impl Source {
+    /// names of the protocols (or paths of the files) in the order of the outputs
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            Source::Birdc { protos, .. } | Source::Socket { protos, .. } => protos.clone(),
+            Source::Files(paths) => paths.iter().map(|i| i.display().to_string()).collect(),
+        }
+    }
+
+    fn fetch(&self) -> Vec<Result<String, GatherError>> {
+        match self {
+            Source::Birdc { birdc, protos } => fetch_parallel(protos, |i| run_birdc(birdc, i)),
+            Source::Socket {
+                path,
+                protos,
+                timeout,
+            } => fetch_parallel(protos, |i| query_socket(path, i, *timeout)),
+            Source::Files(paths) => paths
+                .iter()
+                .map(|i| {
+                    std::fs::read_to_string(i).map_err(|err| GatherError::ReadFile {
+                        path: i.clone(),
+                        err,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Fetches the birdc outputs from the given source, together with the name
+/// of the protocol (or file) they belong to. Failures of single protocols
+/// are logged, an error is only returned if no output is left.
+pub fn gather_outputs(source: &Source) -> Result<Vec<(String, String)>, GatherError> {
+    let mut outputs = Vec::new();
+    let mut first_err = None;
+    for (name, i) in source.names().into_iter().zip(source.fetch()) {
+        match i {
+            Ok(x) => outputs.push((name, x)),
+            Err(x) => {
+                error!("gather: {}", x);
+                first_err.get_or_insert(x);
+            }
+        }
+    }
+    match first_err {
+        Some(x) if outputs.is_empty() => Err(x),
+        _ => Ok(outputs),
+    }
+}
+
+/// Gathers the topology from the given source. Failures of single protocols
+/// are logged, an error is only returned if no usable data is left.
+pub fn gather(source: &Source, opts: &ParseOptions) -> Result<Graph, GatherError> {
+    let outputs = gather_outputs(source)?;
+    build_graph_named(outputs.iter().map(|(name, x)| (&name[..], &x[..])), opts)
+}
+
+/// Caches the outputs of a source for a short time, so that the frequency of
+/// birdc invocations is bounded, regardless of how often a refresh is requested.
+#[derive(Debug)]
+pub struct OutputCache {
+    ttl: Duration,
+    last: Option<(Instant, Vec<(String, String)>)>,
+}
+
+impl OutputCache {
+    pub fn new(ttl: Duration) -> Self {
+        OutputCache { ttl, last: None }
+    }
+
+    /// Like [`gather_outputs`], but reuses the outputs of the previous call
+    /// if they are younger than the TTL. Failures aren't cached.
+    pub fn get(&mut self, source: &Source) -> Result<Vec<(String, String)>, GatherError> {
+        if let Some((at, outputs)) = &self.last {
+            if at.elapsed() < self.ttl {
+                return Ok(outputs.clone());
+            }
         }
-        tmp.push(String::from_utf8(outp.stdout).expect("got non-utf8 birdc output"));
+        self.last = None;
+        let outputs = gather_outputs(source)?;
+        self.last = Some((Instant::now(), outputs.clone()));
+        Ok(outputs)
     }
+}
+
+/// Builds the graph from the given birdc outputs (one per protocol).
+/// Outputs which fail to parse are skipped, the graph is built
+/// as long as at least one of them could be parsed.
+pub fn build_graph(outputs: &[String], opts: &ParseOptions) -> Result<Graph, GatherError> {
+    build_graph_(outputs.iter().map(|x| (None, &x[..])), opts)
+}
+
+/// Like [`build_graph`], but the outputs are named by their protocol,
+/// the protocols in which a node appears are listed in `details["protos"]`.
+pub fn build_graph_named<'a>(
+    outputs: impl Iterator<Item = (&'a str, &'a str)>,
+    opts: &ParseOptions,
+) -> Result<Graph, GatherError> {
+    build_graph_(outputs.map(|(name, x)| (Some(name), x)), opts)
+}
+
+fn build_graph_<'a>(
+    outputs: impl Iterator<Item = (Option<&'a str>, &'a str)>,
+    opts: &ParseOptions,
+) -> Result<Graph, GatherError> {
     let mut topo = crate::parser::Topology::new();
-    for i in tmp.iter() {
-        topo = match crate::parser::parse_topology(topo, i) {
-            Ok(topo) => topo,
+    let mut protos: IdMap<u64, BTreeSet<&str>> = IdMap::default();
+    let mut parsed_any = false;
+    let mut last_err = None;
+    for (name, i) in outputs {
+        // the outputs are parsed separately and merged afterwards, because
+        // the distances differ between e.g. the OSPFv2 and OSPFv3 instances
+        let res = crate::parser::parse_topology_with(crate::parser::Topology::new(), i, opts)
+            .and_then(|x| {
+                let ids: Vec<u64> = x.interned.keys().copied().collect();
+                topo.merge(x)?;
+                Ok(ids)
+            });
+        match res {
+            Ok(ids) => {
+                if let Some(name) = name {
+                    for id in ids {
+                        protos.entry(id).or_default().insert(name);
+                    }
+                }
+                parsed_any = true;
+            }
             Err(x) => {
                 error!("gather: parsing birdc output failed ({}):\n{}", x, i);
-                return None;
+                last_err = Some(x.to_string());
             }
-        };
+        }
+    }
+    if !parsed_any {
+        if let Some(x) = last_err {
+            return Err(GatherError::Parse(x));
+        }
     }
     if topo.areas.is_empty() {
-        return None;
+        return Err(GatherError::Empty);
+    }
+    if let Some(v) = &topo.bird_version {
+        debug!("gather: topology data produced by BIRD v{}", v);
     }
-    let mut nodes_: HashMap<u64, (&str, bool, Map<String, Value>)> = topo
+    let n = topo.interned.len();
+    let mut nodes_: IdMap<u64, NodeInfo<'_>> = topo
         .interned
         .iter()
-        .map(|(&k, &v)| (k, (v, false, Map::new())))
+        .map(|(&k, &v)| (k, (v, false, Map::new(), None)))
         .collect();
-    let mut nodes: HashMap<u64, Node> = HashMap::new();
+    // sorted by id, so that the output is deterministic
+    let mut nodes: BTreeMap<u64, Node> = BTreeMap::new();
     let mut edges: Vec<Edge> = Vec::new();
-    if let Some(bb_area) = topo.areas.get("0.0.0.0") {
-        let mut insert_edge = |id1, id2, w| {
+    // costs of the router-to-router links per direction, keyed by (area, from, to)
+    let mut costs: IdMap<(&str, u64, u64), Vec<Metric>> =
+        IdMap::with_capacity_and_hasher(2 * n, Default::default());
+    // areas in which the nodes appear (either with their own entry, or as link target)
+    let mut node_areas: IdMap<u64, BTreeSet<&str>> =
+        IdMap::with_capacity_and_hasher(n, Default::default());
+
+    // routers which are present in multiple areas are area border routers,
+    // those which originate external routes are AS boundary routers
+    // (which are also announced into other areas via xrouter entries)
+    let mut router_areas: IdMap<u64, usize> =
+        IdMap::with_capacity_and_hasher(n, Default::default());
+    let mut asbrs: IdSet<u64> = IdSet::default();
+    for area in topo.areas.values() {
+        for (&rid, router) in area.routers.iter() {
+            *router_areas.entry(rid).or_default() += 1;
+            for i in router.entries() {
+                match i.typ {
+                    EntryType::External => {
+                        asbrs.insert(rid);
+                    }
+                    EntryType::XRouter => {
+                        asbrs.insert(i.id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    for (k, v) in nodes_.iter_mut() {
+        if router_areas.get(k).copied().unwrap_or(0) > 1 {
+            v.2.insert("abr".to_string(), Value::Bool(true));
+        }
+        if asbrs.contains(k) {
+            v.2.insert("asbr".to_string(), Value::Bool(true));
+        }
+    }
+
+    // iterate the areas in a stable order, so that the backbone area (if present)
+    // comes first and the output is deterministic
+    let mut areas: Vec<_> = topo.areas.iter().collect();
+    areas.sort_by_key(|&(&area_name, _)| area_name);
+
+    // transit networks which are present in multiple areas become a single
+    // node (with the union of the members), thus they need a consistent DR
+    let mut network_drs: IdMap<u64, u64> = IdMap::default();
+    for &(&area_name, area) in areas.iter() {
+        for (&nid, network) in area.networks.iter().filter(|(_, i)| i.dr != 0) {
+            let dr = *network_drs.entry(nid).or_insert(network.dr);
+            if dr != network.dr {
+                warn!(
+                    "gather: network {} has another DR in area {} ({}), keeping {}",
+                    topo.interned[&nid], area_name, topo.interned[&network.dr], topo.interned[&dr]
+                );
+            }
+        }
+    }
+
+    for (&area_name, area) in areas {
+        let components = topo.components(area_name).len();
+        if components > 1 {
+            warn!(
+                "gather: area {} is partitioned into {} parts",
+                area_name, components
+            );
+        }
+        let mut insert_edge = |id1, id2, m: Metric, virtual_link| {
+            if id1 == id2 {
+                // e.g. a router which lists itself as neighbor
+                debug!(
+                    "gather: dropping self-loop of {} in area {}",
+                    id1, area_name
+                );
+                return;
+            }
+            let (metric, external) = (m.value(), m.is_external());
+            let w = if external {
+                metric.saturating_add(EXTERNAL_METRIC_OFFSET)
+            } else {
+                metric
+            };
             edges.push(Edge {
                 from: std::cmp::min(id1, id2),
                 to: std::cmp::max(id1, id2),
-                length: std::cmp::min(w / 100 + 1, 1000),
+                length: display_length(w),
+                metric,
+                external,
+                area: area_name.to_string(),
+                asymmetric: false,
+                virtual_link,
             });
         };
-        for (&rid, router) in bb_area.routers.iter() {
-            let mut roun = nodes_.get_mut(&rid).unwrap();
-            roun.1 = !router.is_unreachable();
-            roun.2 = router.get_details();
-            for (i, w) in router.neighbors() {
-                let orid = crate::parser::router2id(i);
-                insert_edge(rid, orid, w);
-            }
-            for (i, w) in router.conns() {
-                let orid = crate::parser::router2id(i);
+        for (&rid, router) in area.routers.iter() {
+            let roun = nodes_.get_mut(&rid).unwrap();
+            roun.1 |= !router.is_unreachable();
+            merge_details(&mut roun.2, router.get_details());
+            roun.3.get_or_insert(area_name);
+            node_areas.entry(rid).or_default().insert(area_name);
+            for (i, orid, w) in router.neighbors() {
+                node_areas.entry(orid).or_default().insert(area_name);
+                // placeholder for neighbors without their own router entry,
+                // gets replaced below if the neighbor is known
+                nodes.entry(orid).or_insert_with(|| Node {
+                    id: orid,
+                    label: i.to_string(),
+                    group: group::ROUTER.to_string(),
+                    area: Some(area_name.to_string()),
+                    details: Map::new(),
+                });
+                costs.entry((area_name, rid, orid)).or_default().push(w);
+                insert_edge(rid, orid, w, false);
+            }
+            for (i, orid, w) in router.vlinks() {
+                node_areas.entry(orid).or_default().insert(area_name);
+                nodes.entry(orid).or_insert_with(|| Node {
+                    id: orid,
+                    label: i.to_string(),
+                    group: group::ROUTER.to_string(),
+                    area: Some(area_name.to_string()),
+                    details: Map::new(),
+                });
+                insert_edge(rid, orid, w, true);
+            }
+            for (i, orid, typ, w) in router.conns() {
+                node_areas.entry(orid).or_default().insert(area_name);
+                // transit networks have their own network entry,
+                // which replaces this placeholder below
                 nodes.entry(orid).or_insert_with(|| Node {
                     id: orid,
                     label: i.to_string(),
-                    group: "network".to_string(),
+                    group: match typ {
+                        EntryType::StubNet => group::STUBNET,
+                        _ => group::NETWORK,
+                    }
+                    .to_string(),
+                    area: Some(area_name.to_string()),
                     details: Map::new(),
                 });
-                insert_edge(rid, orid, w);
+                insert_edge(rid, orid, w, false);
             }
         }
-        for (&nid, network) in bb_area.networks.iter() {
-            let mut ntwn = nodes_.get_mut(&nid).unwrap();
-            ntwn.1 = !network.is_unreachable();
-            ntwn.2.insert(
-                "distance".to_string(),
-                Value::Number(network.distance.into()),
-            );
-            for i in network
-                .routers
-                .iter()
-                .copied()
-                .chain(std::iter::once(network.dr))
-            {
-                insert_edge(nid, i, 0);
+        for (&nid, network) in area.networks.iter() {
+            let ntwn = nodes_.get_mut(&nid).unwrap();
+            ntwn.1 |= !network.is_unreachable();
+            ntwn.2
+                .entry("distance")
+                .or_insert_with(|| Value::Number(network.distance.into()));
+            let dr = network_drs.get(&nid).copied();
+            if let Some(dr) = dr {
+                ntwn.2
+                    .entry("dr")
+                    .or_insert_with(|| Value::String(topo.interned[&dr].to_string()));
+            }
+            ntwn.3.get_or_insert(area_name);
+            node_areas.entry(nid).or_default().insert(area_name);
+            for i in network.routers.iter().copied().chain(dr) {
+                insert_edge(nid, i, Metric::Internal(0), false);
             }
         }
     }
-    nodes.extend(nodes_.iter().map(|(&k, v)| {
+    // transit networks are named by their prefix in OSPFv2, but by the
+    // id of their DR and its interface (e.g. `[10.0.0.1-3]`) in OSPFv3
+    let transit: IdSet<u64> = topo
+        .areas
+        .values()
+        .flat_map(|area| area.networks.keys().copied())
+        .collect();
+    nodes.extend(nodes_.into_iter().map(|(k, v)| {
         (
             k,
             Node {
                 id: k,
                 label: v.0.to_string(),
                 group: if !v.1 {
-                    "unreachable"
-                } else if v.0.contains('/') {
-                    "network"
+                    group::UNREACHABLE
+                } else if v.0.contains('/') || transit.contains(&k) {
+                    group::NETWORK
+                } else if asbrs.contains(&k) {
+                    group::ASBR
+                } else if router_areas.get(&k).copied().unwrap_or(0) > 1 {
+                    group::ABR
                 } else {
-                    "ytrizja"
+                    group::ROUTER
                 }
                 .to_string(),
-                details: v.2.clone(),
+                area: v.3.map(str::to_string),
+                details: v.2,
             },
         )
     }));
+    for (id, node) in nodes.iter_mut() {
+        if let Some(protos) = protos.get(id) {
+            let protos = protos
+                .iter()
+                .map(|&i| Value::String(i.to_string()))
+                .collect();
+            node.details
+                .insert("protos".to_string(), Value::Array(protos));
+        }
+        if let Some(areas) = node_areas.get(id) {
+            let areas = areas
+                .iter()
+                .map(|&i| Value::String(i.to_string()))
+                .collect();
+            node.details
+                .insert("areas".to_string(), Value::Array(areas));
+        }
+    }
+    for i in costs.values_mut() {
+        i.sort_unstable();
+    }
+    for i in edges.iter_mut() {
+        let fwd = costs.get(&(&i.area[..], i.from, i.to));
+        let bwd = costs.get(&(&i.area[..], i.to, i.from));
+        if let (Some(fwd), Some(bwd)) = (fwd, bwd) {
+            i.asymmetric = fwd != bwd;
+        }
+    }
     edges.sort();
-    edges.dedup();
-
-    let nodes: Vec<Node> = nodes.values().cloned().collect();
-    let mut ret = Map::new();
-    ret.insert(
-        "nodes".to_string(),
-        serde_json::to_value(&nodes).expect("unable to serialize nodes"),
-    );
-    ret.insert(
-        "edges".to_string(),
-        serde_json::to_value(&edges).expect("unable to serialize edges"),
-    );
-    Some(serde_json::to_string(&ret).expect("unable to serialize data"))
+    // e.g. a link which both routers report; links which are present in
+    // multiple areas are kept once per area, so that each area is complete
+    edges.dedup_by(|a, b| {
+        (a.from, a.to, a.metric, a.external, &a.area, a.virtual_link)
+            == (b.from, b.to, b.metric, b.external, &b.area, b.virtual_link)
+    });
+
+    let mut graph = Graph {
+        bird_version: topo.bird_version.clone(),
+        edges,
+        nodes: nodes.into_values().collect(),
+        schema_version: SCHEMA_VERSION,
+    };
+    // every referenced node should have a (placeholder) node,
+    // `lenient` covers both the parser and this check
+    graph.check_edges(!opts.lenient)?;
+    graph.update_degrees();
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SIMPLE: &str = "BIRD v2.0.7 ready.
+
+area 0.0.0.0
+
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+
+    #[test]
+    fn test_partial_failure() {
+        let graph = build_graph(
+            &[SIMPLE.to_string(), "garbage".to_string()],
+            &Default::default(),
+        )
+        .expect("valid output got discarded");
+        let mut labels: Vec<_> = graph.nodes.iter().map(|i| &i.label[..]).collect();
+        labels.sort();
+        assert_eq!(labels, ["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(graph.edges.len(), 1);
+
+        assert!(matches!(
+            build_graph(&["garbage".to_string()], &Default::default()),
+            Err(GatherError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_stubnet_group() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tnetwork 10.0.0.0/24 metric 10
+\t\tstubnet 10.0.1.0/24 metric 10
+\tnetwork 10.0.0.0/24
+\t\tdr 10.0.0.1
+\t\tdistance 10
+\t\trouter 10.0.0.1
+";
+        let mut graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let group = |graph: &Graph, label: &str| {
+            graph
+                .nodes
+                .iter()
+                .find(|i| i.label == label)
+                .map(|i| i.group.clone())
+        };
+        assert_eq!(group(&graph, "10.0.0.0/24").as_deref(), Some("network"));
+        assert_eq!(group(&graph, "10.0.1.0/24").as_deref(), Some("stubnet"));
+
+        let names = [("network".to_string(), "transit".to_string())];
+        graph.rename_groups(&names.iter().cloned().collect());
+        assert_eq!(group(&graph, "10.0.0.0/24").as_deref(), Some("transit"));
+        assert_eq!(group(&graph, "10.0.1.0/24").as_deref(), Some("stubnet"));
+    }
+
+    #[test]
+    fn test_asymmetric() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\t\trouter 10.0.0.3 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 500
+\trouter 10.0.0.3
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let id = crate::parser::router2id;
+        let edges = |a: &str, b: &str| {
+            let (a, b) = (id(a), id(b));
+            let (from, to) = (std::cmp::min(a, b), std::cmp::max(a, b));
+            graph
+                .edges
+                .iter()
+                .filter(|i| (i.from, i.to) == (from, to))
+                .map(|i| (i.metric, i.asymmetric))
+                .collect::<Vec<_>>()
+        };
+        // both directions are kept, because their costs differ
+        assert_eq!(edges("10.0.0.1", "10.0.0.2"), [(10, true), (500, true)]);
+        assert_eq!(edges("10.0.0.1", "10.0.0.3"), [(10, false)]);
+    }
+
+    #[test]
+    fn test_external_metric() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\texternal 0.0.0.0/0 metric2 10
+\t\texternal 10.9.0.0/16 metric2 16777215
+\t\tstubnet 10.0.1.0/24 metric 5000
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let edge = |label: &str| {
+            let id = crate::parser::router2id(label);
+            graph
+                .edges
+                .iter()
+                .find(|i| i.from == id || i.to == id)
+                .map(|i| (i.metric, i.external, i.length))
+        };
+        // the raw cost is kept, only the display length includes the offset
+        assert_eq!(edge("0.0.0.0/0"), Some((10, true, 11)));
+        assert_eq!(edge("10.0.1.0/24"), Some((5000, false, 51)));
+        // the largest type 2 metric, 1000 + 100 * log2((16777215 + 1000) / 100000)
+        assert_eq!(edge("10.9.0.0/16"), Some((16_777_215, true, 1739)));
+    }
+
+    #[test]
+    fn test_display_length() {
+        assert_eq!(display_length(0), 1);
+        assert_eq!(display_length(99), 1);
+        assert_eq!(display_length(100), 2);
+        assert_eq!(display_length(99_999), 1000);
+        assert_eq!(display_length(100_000), 1000);
+        assert_eq!(display_length(200_000), 1100);
+        assert_eq!(display_length(400_000), 1200);
+        // the largest costs are still distinguishable
+        assert!(display_length(u32::MAX - 1) > display_length(u32::MAX / 2));
+        assert!(display_length(u32::MAX) < 2600);
+    }
+
+    #[test]
+    fn test_border_routers() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\t\texternal 0.0.0.0/0 metric2 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+area 0.0.0.1
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.3 metric 10
+\t\txrouter 10.0.0.1 metric 10
+\trouter 10.0.0.3
+\t\tdistance 20
+\t\trouter 10.0.0.2 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let node = |label: &str| graph.nodes.iter().find(|i| i.label == label).unwrap();
+        let flag =
+            |label: &str, key: &str| node(label).details.get(key) == Some(&Value::Bool(true));
+
+        assert_eq!(node("10.0.0.1").group, "asbr");
+        assert!(flag("10.0.0.1", "asbr") && !flag("10.0.0.1", "abr"));
+        assert_eq!(node("10.0.0.2").group, "abr");
+        assert!(flag("10.0.0.2", "abr") && !flag("10.0.0.2", "asbr"));
+        assert_eq!(node("10.0.0.3").group, "ytrizja");
+        assert!(!flag("10.0.0.3", "abr") && !flag("10.0.0.3", "asbr"));
+    }
+
+    #[test]
+    fn test_parallel_edges() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\t\trouter 10.0.0.2 metric 20
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+\t\trouter 10.0.0.1 metric 20
+";
+        let mut graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let metrics = |graph: &Graph| graph.edges.iter().map(|i| i.metric).collect::<Vec<_>>();
+        assert_eq!(metrics(&graph), [10, 20]);
+
+        graph.aggregate_parallel_edges();
+        assert_eq!(metrics(&graph), [10]);
+        assert!(!graph.edges[0].asymmetric);
+    }
+
+    #[test]
+    fn test_self_loop() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.1 metric 1
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges.len(), 1);
+        assert!(graph.edges.iter().all(|i| i.from != i.to));
+    }
+
+    #[test]
+    fn test_degree() {
+        // 10.0.0.1 in the center, connected to three others
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\t\trouter 10.0.0.3 metric 10
+\t\trouter 10.0.0.4 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+\trouter 10.0.0.3
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+\trouter 10.0.0.4
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let degree = |label: &str| {
+            graph
+                .nodes
+                .iter()
+                .find(|i| i.label == label)
+                .map(|i| i.details["degree"].clone())
+                .unwrap()
+        };
+        assert_eq!(degree("10.0.0.1"), 3);
+        assert_eq!(degree("10.0.0.2"), 1);
+        assert_eq!(degree("10.0.0.4"), 1);
+    }
+
+    #[test]
+    fn test_shared_network() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tnetwork 10.0.9.0/24 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\tnetwork 10.0.9.0/24 metric 10
+\tnetwork 10.0.9.0/24
+\t\tdr 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1
+\t\trouter 10.0.0.2
+area 0.0.0.1
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\tnetwork 10.0.9.0/24 metric 10
+\trouter 10.0.0.3
+\t\tdistance 20
+\t\tnetwork 10.0.9.0/24 metric 10
+\tnetwork 10.0.9.0/24
+\t\tdr 10.0.0.3
+\t\tdistance 10
+\t\trouter 10.0.0.2
+\t\trouter 10.0.0.3
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let nid = crate::parser::router2id("10.0.9.0/24");
+        let networks: Vec<_> = graph.nodes.iter().filter(|i| i.id == nid).collect();
+        assert_eq!(networks.len(), 1);
+        assert_eq!(
+            networks[0].details["areas"],
+            serde_json::json!(["0.0.0.0", "0.0.0.1"])
+        );
+        // the DR of the first area is kept
+        assert_eq!(networks[0].details["dr"], "10.0.0.2");
+
+        let mut members: Vec<_> = graph
+            .edges
+            .iter()
+            .filter(|i| i.metric == 0 && (i.from == nid || i.to == nid))
+            .map(|i| (&i.area[..], if i.from == nid { i.to } else { i.from }))
+            .collect();
+        members.sort_unstable();
+        // the router which is attached in both areas is a member in both
+        let mut expected: Vec<_> = [
+            ("0.0.0.0", "10.0.0.1"),
+            ("0.0.0.0", "10.0.0.2"),
+            ("0.0.0.1", "10.0.0.2"),
+            ("0.0.0.1", "10.0.0.3"),
+        ]
+        .iter()
+        .map(|&(area, i)| (area, crate::parser::router2id(i)))
+        .collect();
+        expected.sort_unstable();
+        assert_eq!(members, expected);
+    }
+
+    #[test]
+    fn test_protos() {
+        let v4 = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        // independent SPF, thus other distances
+        let v6 = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 20
+\t\trouter 10.0.0.3 metric 10
+\trouter 10.0.0.2
+\t\tdistance 20
+\t\trouter 10.0.0.1 metric 20
+\trouter 10.0.0.3
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        let graph = build_graph_named(
+            vec![("ospf4", v4), ("ospf6", v6)].into_iter(),
+            &Default::default(),
+        )
+        .unwrap();
+        let protos = |label: &str| {
+            graph
+                .nodes
+                .iter()
+                .find(|i| i.label == label)
+                .map(|i| i.details["protos"].clone())
+                .unwrap()
+        };
+        assert_eq!(protos("10.0.0.2"), serde_json::json!(["ospf4", "ospf6"]));
+        assert_eq!(protos("10.0.0.3"), serde_json::json!(["ospf6"]));
+        // both links between 10.0.0.1 and 10.0.0.2 are kept
+        let a = crate::parser::router2id("10.0.0.1");
+        let b = crate::parser::router2id("10.0.0.2");
+        let metrics: Vec<_> = graph
+            .edges
+            .iter()
+            .filter(|i| (i.from, i.to) == (a.min(b), a.max(b)))
+            .map(|i| i.metric)
+            .collect();
+        assert_eq!(metrics, [10, 20]);
+
+        // unnamed outputs don't list protocols
+        let graph = build_graph(&[v4.to_string(), v6.to_string()], &Default::default()).unwrap();
+        assert!(graph
+            .nodes
+            .iter()
+            .all(|i| !i.details.contains_key("protos")));
+    }
+
+    #[test]
+    fn test_check_edges() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+";
+        let mut graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let edges = graph.edges.clone();
+        assert!(graph.check_edges(true).is_ok());
+        assert_eq!(graph.edges, edges);
+
+        let mut dangling = edges[0].clone();
+        dangling.to = 1;
+        graph.edges.push(dangling);
+        assert!(matches!(
+            graph.clone().check_edges(true),
+            Err(GatherError::DanglingEdge { to: 1, .. })
+        ));
+        assert!(graph.check_edges(false).is_ok());
+        assert_eq!(graph.edges, edges);
+    }
+
+    #[test]
+    fn test_vlink() {
+        // 10.0.0.1 and 10.0.0.3 are connected by a virtual link through area 0.0.0.1
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tvlink 10.0.0.3 metric 20
+\trouter 10.0.0.3
+\t\tdistance 20
+\t\tvlink 10.0.0.1 metric 20
+area 0.0.0.1
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+\t\trouter 10.0.0.3 metric 10
+\trouter 10.0.0.3
+\t\tdistance 20
+\t\trouter 10.0.0.2 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let vlinks: Vec<_> = graph.edges.iter().filter(|i| i.virtual_link).collect();
+        assert_eq!(vlinks.len(), 1);
+        let (a, b) = (
+            crate::parser::router2id("10.0.0.1"),
+            crate::parser::router2id("10.0.0.3"),
+        );
+        assert_eq!((vlinks[0].from, vlinks[0].to), (a.min(b), a.max(b)));
+        assert_eq!(vlinks[0].area, "0.0.0.0");
+        assert_eq!(vlinks[0].metric, 20);
+        assert_eq!(graph.edges.len(), 3);
+        // no placeholder network nodes
+        assert_eq!(graph.nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_retain_areas() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+area 0.0.0.1
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.3 metric 10
+\trouter 10.0.0.3
+\t\tdistance 20
+\t\trouter 10.0.0.2 metric 10
+";
+        let mut graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        graph.retain_areas(&["0.0.0.1".to_string(), "0.0.0.9".to_string()]);
+        let mut labels: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|i| (&i.label[..], i.area.as_deref(), &i.group[..]))
+            .collect();
+        labels.sort();
+        // the ABR stays one
+        assert_eq!(
+            labels,
+            [
+                ("10.0.0.2", Some("0.0.0.1"), group::ABR),
+                ("10.0.0.3", Some("0.0.0.1"), group::ROUTER)
+            ]
+        );
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].area, "0.0.0.1");
+        assert!(graph.nodes.iter().all(|i| i.details["degree"] == 1));
+    }
+
+    #[test]
+    fn test_link_in_two_areas() {
+        let area = |name| {
+            format!(
+                "area {}
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+",
+                name
+            )
+        };
+        let s = format!("BIRD v2.0.7 ready.\n{}{}", area("0.0.0.0"), area("0.0.0.1"));
+        let graph = build_graph(&[s], &Default::default()).unwrap();
+        let areas: Vec<_> = graph.edges.iter().map(|i| &i.area[..]).collect();
+        assert_eq!(areas, ["0.0.0.0", "0.0.0.1"]);
+        for name in &["0.0.0.0", "0.0.0.1"] {
+            let mut graph = graph.clone();
+            graph.retain_areas(&[name.to_string()]);
+            assert_eq!(graph.edges.len(), 1);
+            assert_eq!(graph.nodes.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_areas() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+area 0.0.0.1
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.3 metric 10
+\trouter 10.0.0.3
+\t\tdistance 20
+\t\trouter 10.0.0.2 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let areas = |label: &str| {
+            graph
+                .nodes
+                .iter()
+                .find(|i| i.label == label)
+                .map(|i| i.details["areas"].clone())
+                .unwrap()
+        };
+        assert_eq!(areas("10.0.0.1"), serde_json::json!(["0.0.0.0"]));
+        assert_eq!(areas("10.0.0.2"), serde_json::json!(["0.0.0.0", "0.0.0.1"]));
+        assert_eq!(areas("10.0.0.3"), serde_json::json!(["0.0.0.1"]));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: Value =
+            serde_json::from_str(include_str!("../schema/graph.schema.json")).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+
+        // the serialized fields match the documented ones exactly
+        let keys = |x: &Value| -> Vec<String> {
+            let mut ret: Vec<_> = x.as_object().unwrap().keys().cloned().collect();
+            ret.sort();
+            ret
+        };
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        let graph = build_graph(&[s.to_string()], &Default::default()).unwrap();
+        let graph = serde_json::to_value(&graph).unwrap();
+        assert_eq!(keys(&graph), keys(&schema["properties"]));
+        let defs = &schema["definitions"];
+        for (kind, items) in &[("node", &graph["nodes"]), ("edge", &graph["edges"])] {
+            let items = items.as_array().unwrap();
+            assert!(!items.is_empty());
+            for i in items {
+                assert_eq!(keys(i), keys(&defs[kind]["properties"]), "{}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_cache() {
+        let path = std::env::temp_dir().join(format!("bird2topo-cache-{}", std::process::id()));
+        let source = Source::Files(vec![path.clone()]);
+        std::fs::write(&path, "old").unwrap();
+
+        let mut cache = OutputCache::new(Duration::from_secs(3600));
+        assert_eq!(cache.get(&source).unwrap()[0].1, "old");
+        std::fs::write(&path, "new").unwrap();
+        assert_eq!(cache.get(&source).unwrap()[0].1, "old");
+
+        let mut cache = OutputCache::new(Duration::default());
+        assert_eq!(cache.get(&source).unwrap()[0].1, "new");
+        std::fs::remove_file(&path).unwrap();
+        assert!(cache.get(&source).is_err());
+    }
 }
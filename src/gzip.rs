@@ -0,0 +1,67 @@
+//! gzip compression of HTTP responses
+
+use flate2::{write::GzEncoder, Compression};
+use std::io::Write;
+
+/// whether the given `Accept-Encoding` header allows gzip
+pub fn accepted(header: Option<&[u8]>) -> bool {
+    let header = match header.and_then(|x| std::str::from_utf8(x).ok()) {
+        Some(x) => x,
+        None => return false,
+    };
+    header.split(',').any(|i| {
+        let mut parts = i.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        // `q=0` means "not acceptable"
+        let refused = parts.any(|p| {
+            p.strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .map(|q| q <= 0.0)
+                .unwrap_or(false)
+        });
+        !refused
+            && ["gzip", "x-gzip", "*"]
+                .iter()
+                .any(|j| j.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Compresses `data` into the gzip format.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 4), Compression::default());
+    encoder
+        .write_all(data)
+        .and_then(|()| encoder.finish())
+        .expect("unable to compress into memory")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_accepted() {
+        assert!(accepted(Some(b"gzip, deflate, br")));
+        assert!(accepted(Some(b"deflate;q=1.0, GZIP;q=0.5")));
+        assert!(accepted(Some(b"*")));
+        assert!(!accepted(Some(b"gzip;q=0, deflate")));
+        assert!(!accepted(Some(b"identity")));
+        assert!(!accepted(None));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let topology = "{\"from\": 1, \"to\": 2, \"area\": \"0.0.0.0\"}, ".repeat(500);
+        for data in &[&b""[..], b"a", topology.as_bytes()] {
+            let gz = compress(data);
+            assert_eq!(gz[..3], [0x1f, 0x8b, 8]);
+            let mut out = Vec::new();
+            GzDecoder::new(&gz[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(&out[..], *data);
+        }
+        // the repetitive topology JSON shrinks to a fraction of its size
+        assert!(compress(topology.as_bytes()).len() < topology.len() / 10);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::gather::Graph;
+use crate::protocol::{Stamp, Stamped};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// default number of retained revisions
+pub const DEFAULT_CAPACITY: usize = 64;
+/// upper limit of the capacity, each revision keeps a whole graph in memory
+pub const MAX_CAPACITY: usize = 10_000;
+
+/// summary of a retained revision, as listed by `/history`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// The most recent revisions of the topology (e.g. to replay a reconvergence),
+/// the oldest ones get dropped once the capacity is reached.
+#[derive(Clone, Debug)]
+pub struct History {
+    capacity: usize,
+    /// ordered by revision
+    entries: VecDeque<Stamped<Graph>>,
+}
+
+impl History {
+    /// a capacity of 0 disables the history
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            // grows as needed, most instances never fill a large history
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, stamp: Stamp, graph: Graph) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Stamped { stamp, data: graph });
+    }
+
+    pub fn list(&self) -> Vec<Summary> {
+        self.entries
+            .iter()
+            .map(|i| Summary {
+                stamp: i.stamp,
+                nodes: i.data.nodes.len(),
+                edges: i.data.edges.len(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, rev: u64) -> Option<&Stamped<Graph>> {
+        let idx = self
+            .entries
+            .binary_search_by_key(&rev, |i| i.stamp.rev)
+            .ok()?;
+        self.entries.get(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(rev: u64) -> Stamp {
+        Stamp {
+            rev,
+            generated_at: 1000 * rev,
+        }
+    }
+
+    fn graph(version: &str) -> Graph {
+        Graph {
+            bird_version: Some(version.to_string()),
+            schema_version: crate::gather::SCHEMA_VERSION,
+            edges: vec![],
+            nodes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut h = History::new(3);
+        for rev in 1..=5 {
+            h.push(stamp(rev), graph(&rev.to_string()));
+        }
+        let revs: Vec<_> = h.list().iter().map(|i| i.stamp.rev).collect();
+        assert_eq!(revs, [3, 4, 5]);
+        assert!(h.get(2).is_none());
+        assert_eq!(h.get(4).unwrap().data.bird_version.as_deref(), Some("4"));
+        assert_eq!(h.get(4).unwrap().stamp, stamp(4));
+
+        let mut h = History::new(0);
+        h.push(stamp(1), graph("1"));
+        assert!(h.list().is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+//! plain HTTP/1.1 requests and responses, one request per connection
+
+use std::io::{self, Read, Write};
+use tungstenite::http::header::{CONNECTION, CONTENT_LENGTH, CONTENT_TYPE};
+pub use tungstenite::http::{HeaderValue, Request, Response, StatusCode, Version};
+
+/// upper limit of the request head, to avoid unbounded buffering
+const MAX_HEAD_LEN: usize = 16 * 1024;
+/// upper limit of the number of request headers
+const MAX_HEADERS: usize = 64;
+
+fn invalid(msg: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads the request line and headers (the body is ignored). Data which
+/// followed the head right away (e.g. WebSocket frames) is returned too.
+pub fn read_request(mut reader: impl Read) -> io::Result<(Request<()>, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let n = reader.read(&mut chunk)?;
+        if n == 0 {
+            return Err(invalid("incomplete request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        match req.parse(&buf).map_err(invalid)? {
+            httparse::Status::Complete(len) => {
+                let mut builder = Request::builder()
+                    .method(req.method.unwrap_or_default())
+                    .uri(req.path.unwrap_or_default())
+                    .version(match req.version {
+                        Some(0) => Version::HTTP_10,
+                        _ => Version::HTTP_11,
+                    });
+                for i in req.headers.iter() {
+                    builder = builder.header(i.name, i.value);
+                }
+                let req = builder.body(()).map_err(invalid)?;
+                return Ok((req, buf[len..].to_vec()));
+            }
+            httparse::Status::Partial if buf.len() >= MAX_HEAD_LEN => {
+                return Err(invalid("request head too large"));
+            }
+            httparse::Status::Partial => {}
+        }
+    }
+}
+
+/// Returns the value of a request header, if it is present (and valid UTF-8).
+pub fn header<'a, T>(req: &'a Request<T>, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|x| x.to_str().ok())
+}
+
+/// Builds a response with the given content type.
+pub fn response(
+    status: StatusCode,
+    content_type: &str,
+    body: impl Into<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    let mut res = Response::new(body.into());
+    *res.status_mut() = status;
+    if let Ok(x) = HeaderValue::from_str(content_type) {
+        res.headers_mut().insert(CONTENT_TYPE, x);
+    }
+    res
+}
+
+/// Builds a plain text response, e.g. an error message.
+pub fn text(status: StatusCode, body: impl Into<String>) -> Response<Vec<u8>> {
+    response(status, "text/plain", body.into())
+}
+
+/// Writes the response, the connection gets closed afterwards.
+pub fn write_response(mut writer: impl Write, mut res: Response<Vec<u8>>) -> io::Result<()> {
+    let len = res.body().len();
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+    headers.insert(CONNECTION, HeaderValue::from_static("close"));
+    write_head(&mut writer, &res)?;
+    writer.write_all(res.body())?;
+    writer.flush()
+}
+
+/// Writes the status line and headers of the response (e.g. of a WebSocket upgrade).
+pub fn write_head<T>(mut writer: impl Write, res: &Response<T>) -> io::Result<()> {
+    tungstenite::handshake::server::write_response(&mut writer, res).map_err(|x| match x {
+        tungstenite::Error::Io(x) => x,
+        x => invalid(x),
+    })?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_request() {
+        let (req, rest) = read_request(
+            &b"GET /events?x=1 HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer abc\r\n\r\n\x81\x00"[..],
+        )
+        .unwrap();
+        assert_eq!(req.method(), "GET");
+        assert_eq!(req.uri().path(), "/events");
+        assert_eq!(req.uri().query(), Some("x=1"));
+        assert_eq!(req.version(), Version::HTTP_11);
+        assert_eq!(header(&req, "authorization"), Some("Bearer abc"));
+        assert_eq!(header(&req, "Host"), Some("localhost"));
+        assert_eq!(header(&req, "Origin"), None);
+        assert_eq!(rest, b"\x81\x00");
+
+        assert!(read_request(&b"GET /events\r\n\r\n"[..]).is_err());
+        assert!(read_request(&b"GET /events HTTP/1.1\r\nHost: x\r\n"[..]).is_err());
+        let huge = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "x".repeat(MAX_HEAD_LEN));
+        assert!(read_request(huge.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut out = Vec::new();
+        write_response(&mut out, text(StatusCode::NOT_FOUND, "not found\n")).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 404 Not Found\r\ncontent-type: text/plain\r\n\
+             content-length: 10\r\nconnection: close\r\n\r\nnot found\n"
+        );
+    }
+}
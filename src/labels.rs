@@ -0,0 +1,169 @@
+use crate::gather::Graph;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum LabelsError {
+    #[error("unable to read label file {path:?} ({err})")]
+    Read { path: PathBuf, err: String },
+
+    #[error("invalid entry in label file {path:?}, line {line} (expected '<id> <name>')")]
+    Syntax { path: PathBuf, line: usize },
+}
+
+/// Friendly names for router ids (or networks), which replace
+/// their labels in the graph.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Labels(HashMap<String, String>);
+
+impl Labels {
+    /// Reads a mapping file, one `<id> <name>` pair per line (the name
+    /// may contain spaces); empty lines and lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Labels, LabelsError> {
+        let content = std::fs::read_to_string(path).map_err(|e| LabelsError::Read {
+            path: path.to_path_buf(),
+            err: e.to_string(),
+        })?;
+        Labels::parse(&content).map_err(|line| LabelsError::Syntax {
+            path: path.to_path_buf(),
+            line,
+        })
+    }
+
+    /// Parses the content of a mapping file, returns the
+    /// (1-based) number of the first invalid line on error.
+    pub fn parse(s: &str) -> Result<Labels, usize> {
+        let mut ret = HashMap::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.find(char::is_whitespace) {
+                Some(i) => {
+                    ret.insert(line[..i].to_string(), line[i..].trim_start().to_string());
+                }
+                None => return Err(n + 1),
+            }
+        }
+        Ok(Labels(ret))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.0.get(id).map(|x| &x[..])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::iter::FromIterator<(String, String)> for Labels {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Labels {
+        Labels(iter.into_iter().collect())
+    }
+}
+
+impl Graph {
+    /// Replaces the labels of the mapped nodes, the original label
+    /// is kept as `details["raw_id"]`. The node ids stay unchanged.
+    pub fn apply_labels(&mut self, labels: &Labels) {
+        for node in self.nodes.iter_mut() {
+            if let Some(name) = labels.get(&node.label) {
+                let raw = std::mem::replace(&mut node.label, name.to_string());
+                node.details
+                    .insert("raw_id".to_string(), Value::String(raw));
+            }
+        }
+    }
+
+    /// Marks the local router (given by its id or its label)
+    /// via `details["self"]`, returns whether it was found.
+    pub fn mark_self(&mut self, id: &str) -> bool {
+        let mut found = false;
+        for node in self.nodes.iter_mut() {
+            let raw = node.details.get("raw_id").and_then(Value::as_str);
+            if node.label == id || raw == Some(id) {
+                node.details.insert("self".to_string(), Value::Bool(true));
+                found = true;
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gather::build_graph;
+
+    #[test]
+    fn test_parse() {
+        let labels = Labels::parse("# core\n10.0.0.1 core-1\n\n10.0.0.2  edge 2 (old)\n").unwrap();
+        assert_eq!(labels.get("10.0.0.1"), Some("core-1"));
+        assert_eq!(labels.get("10.0.0.2"), Some("edge 2 (old)"));
+        assert_eq!(labels.get("10.0.0.3"), None);
+        assert_eq!(Labels::parse("10.0.0.1 a\n10.0.0.2\n"), Err(2));
+    }
+
+    fn two_routers() -> Graph {
+        build_graph(
+            &["BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+"
+            .to_string()],
+            &Default::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut graph = two_routers();
+        let edges = graph.edges.clone();
+        graph.apply_labels(&Labels::parse("10.0.0.1 core-1").unwrap());
+
+        let mut labels: Vec<_> = graph.nodes.iter().map(|i| &i.label[..]).collect();
+        labels.sort();
+        assert_eq!(labels, ["10.0.0.2", "core-1"]);
+        let node = graph.nodes.iter().find(|i| i.label == "core-1").unwrap();
+        assert_eq!(node.details["raw_id"], "10.0.0.1");
+        assert_eq!(node.id, crate::parser::router2id("10.0.0.1"));
+        assert_eq!(graph.edges, edges);
+    }
+
+    #[test]
+    fn test_mark_self() {
+        let is_self = |graph: &Graph| -> Vec<String> {
+            graph
+                .nodes
+                .iter()
+                .filter(|i| i.details.get("self") == Some(&Value::Bool(true)))
+                .map(|i| i.label.clone())
+                .collect()
+        };
+
+        let mut graph = two_routers();
+        assert!(graph.mark_self("10.0.0.2"));
+        assert_eq!(is_self(&graph), ["10.0.0.2"]);
+
+        // by the raw id as well as by the label, after relabeling
+        for id in &["10.0.0.1", "core-1"] {
+            let mut graph = two_routers();
+            graph.apply_labels(&Labels::parse("10.0.0.1 core-1").unwrap());
+            assert!(graph.mark_self(id));
+            assert_eq!(is_self(&graph), ["core-1"]);
+        }
+
+        let mut graph = two_routers();
+        assert!(!graph.mark_self("10.0.0.3"));
+        assert!(is_self(&graph).is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+pub mod auth;
+pub mod backoff;
+pub mod birdctl;
+pub mod build_info;
+pub mod config;
+pub mod debounce;
+pub mod deflate;
+pub mod diff;
+pub mod export;
+pub mod gather;
+pub mod gzip;
+pub mod history;
+pub mod http;
+pub mod labels;
+pub mod listener;
+pub mod parser;
+pub mod protocol;
+pub mod resolve;
+pub mod signal;
+pub mod sse;
+pub mod static_files;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod tokens;
+
+pub use gather::Graph;
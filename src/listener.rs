@@ -0,0 +1,162 @@
+//! listener of the WebSocket server, on a TCP port or a Unix domain socket
+
+use crate::config::Listen;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// accepted connection
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+/// Removes the socket file of a previous run, unless some process still listens on it.
+/// Other kinds of files are left alone (binding then fails).
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another process", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Listener {
+    pub fn bind(addr: &Listen) -> io::Result<Listener> {
+        match addr {
+            Listen::Tcp(addr) => TcpListener::bind(addr).map(Listener::Tcp),
+            Listen::Unix(path) => {
+                remove_stale_socket(path)?;
+                UnixListener::bind(path).map(Listener::Unix)
+            }
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(x) => x.accept().map(|(s, _)| Stream::Tcp(s)),
+            Listener::Unix(x) => x.accept().map(|(s, _)| Stream::Unix(s)),
+        }
+    }
+}
+
+impl Stream {
+    /// address of the client, for logging
+    pub fn peer(&self) -> io::Result<String> {
+        match self {
+            Stream::Tcp(x) => Ok(x.peer_addr()?.to_string()),
+            // clients usually don't bind their socket to a path
+            Stream::Unix(x) => Ok(x
+                .peer_addr()?
+                .as_pathname()
+                .map_or_else(|| "<unix socket>".to_string(), |p| p.display().to_string())),
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(x) => x.try_clone().map(Stream::Tcp),
+            Stream::Unix(x) => x.try_clone().map(Stream::Unix),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(x) => x.set_read_timeout(timeout),
+            Stream::Unix(x) => x.set_read_timeout(timeout),
+        }
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(x) => x.set_write_timeout(timeout),
+            Stream::Unix(x) => x.set_write_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(x) => x.read(buf),
+            Stream::Unix(x) => x.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(x) => x.write(buf),
+            Stream::Unix(x) => x.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(x) => x.flush(),
+            Stream::Unix(x) => x.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix() {
+        let dir = std::env::temp_dir().join(format!("bird2topo-listen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ws.sock");
+        let addr = Listen::Unix(path.clone());
+
+        let listener = Listener::bind(&addr).unwrap();
+        // still in use
+        assert_eq!(
+            Listener::bind(&addr).err().map(|e| e.kind()),
+            Some(io::ErrorKind::AddrInUse)
+        );
+        // the connection which detected that
+        let mut buf = [0; 4];
+        assert_eq!(listener.accept().unwrap().read(&mut buf).unwrap(), 0);
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        let mut stream = listener.accept().unwrap();
+        assert_eq!(stream.peer().unwrap(), "<unix socket>");
+        client.write_all(b"ping").unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        drop(stream);
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+
+        // the socket file of the dropped listener is stale
+        drop(listener);
+        drop(client);
+        let listener = Listener::bind(&addr).unwrap();
+        drop(listener);
+
+        // other files aren't removed
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "").unwrap();
+        assert!(Listener::bind(&addr).is_err());
+        assert!(path.is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
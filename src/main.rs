@@ -1,128 +1,1019 @@
 use crossbeam_channel as chan;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, RwLock};
 use std::thread::spawn;
 use std::time::{Duration, Instant};
-use tracing::debug;
+use tracing::{debug, error, info, warn};
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Role, WebSocketConfig};
+use tungstenite::{Message, WebSocket};
 
-mod gather;
-mod parser;
-mod tokens;
+use bird2topo::auth::Auth;
+use bird2topo::backoff::Backoff;
+use bird2topo::build_info::BUILD_INFO;
+use bird2topo::config::{self, Listen};
+use bird2topo::debounce::Debouncer;
+use bird2topo::deflate::{self, Inflater};
+use bird2topo::diff::{GraphPatch, Transition};
+use bird2topo::export::{self, Format};
+use bird2topo::history::History;
+use bird2topo::http::{self, HeaderValue, Request, Response, StatusCode};
+use bird2topo::labels::Labels;
+use bird2topo::listener::{Listener, Stream};
+use bird2topo::parser::ParseOptions;
+use bird2topo::protocol::{ClientCommand, Envelope, MessageType, Stamp, Stamped, Status};
+use bird2topo::resolve::Resolver;
+use bird2topo::sse;
+use bird2topo::static_files::StaticFiles;
+#[cfg(feature = "tls")]
+use bird2topo::tls;
+use bird2topo::tokens::{TokenValue, Tokens};
+use bird2topo::{gather, gzip, Graph};
 
-static OSPF_PROTOS: &[&str] = &["ytrizja", "ytrizja_v6"];
+/// minimum time between two gather cycles (e.g. when clients request refreshes)
+const MIN_LOOP_TIME: Duration = Duration::from_millis(100);
+/// upper limit of the time between two gather attempts while these fail
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// how long clients may take to send their request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// how long writes to stalled clients may block
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// how often WebSocket connections check for updates while waiting for messages
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// upper limit of (reassembled) messages of WebSocket clients, commands are tiny
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+/// how long the connections get to close during shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+/// how long reverse DNS lookups are cached
+const DNS_TTL: Duration = Duration::from_secs(600);
 
-use crate::tokens::{TokenGuard, TokenValue, Tokens};
+/// latest topology data, written by the gather loop, readable by any handler
+type Snapshot = Arc<RwLock<Option<Stamped<Graph>>>>;
 
-enum Handler {
-    PreOpen {
-        ws_sender: ws::Sender,
-        tokens: Tokens<ws::Sender>,
-    },
-    PreRunning,
-    Running {
-        tg: TokenGuard<ws::Sender>,
-    },
-    Closed,
+/// when the topology was last gathered successfully (initially the start time)
+type LastSuccess = Arc<RwLock<Instant>>;
+
+/// connected clients, as seen by the gather loop
+#[derive(Clone)]
+enum Client {
+    /// WebSocket, the messages get sent by the thread of the connection
+    Ws(chan::Sender<Message>),
+    /// Server-Sent Events, which only receive whole graphs (formatted as events)
+    Sse(chan::Sender<String>),
 }
 
-impl ws::Handler for Handler {
-    fn on_open(&mut self, shake: ws::Handshake) -> ws::Result<()> {
-        match std::mem::replace(self, Handler::PreRunning) {
-            Handler::PreOpen { ws_sender, tokens } => {
-                if let Some(addr) = shake.remote_addr()? {
-                    debug!("Connection with {} now open", addr);
+impl Client {
+    fn send(&self, msg: String) -> Result<(), String> {
+        match self {
+            Client::Ws(s) => s.send(Message::text(msg)).map_err(|x| x.to_string()),
+            Client::Sse(s) => s.send(msg).map_err(|x| x.to_string()),
+        }
+    }
+
+    fn ping(&self) -> Result<(), String> {
+        match self {
+            Client::Ws(s) => s
+                .send(Message::Ping(Default::default()))
+                .map_err(|x| x.to_string()),
+            Client::Sse(_) => self.send(sse::KEEPALIVE.to_string()),
+        }
+    }
+
+    fn is_ws(&self) -> bool {
+        matches!(self, Client::Ws(_))
+    }
+
+    fn is_sse(&self) -> bool {
+        matches!(self, Client::Sse(_))
+    }
+}
+
+/// Sends `msg` to the clients selected by `to` (e.g. [`Client::is_ws`]),
+/// clients which vanished get dropped.
+fn broadcast(senders: &mut BTreeMap<TokenValue, Client>, msg: &str, to: fn(&Client) -> bool) {
+    senders.retain(|t, s| {
+        !to(s)
+            || match s.send(msg.to_string()) {
+                Ok(()) => true,
+                Err(x) => {
+                    warn!("unable to send message to client {}, dropping it: {}", t, x);
+                    false
                 }
-                *self = Handler::Running {
-                    tg: tokens
-                        .try_acquire(ws_sender)
-                        .expect("unable to acquire token"),
-                };
-                Ok(())
             }
-            _ => panic!("tried to open already opened Handler object"),
+    });
+}
+
+/// client commands, forwarded to the gather loop
+enum ClientRequest {
+    Refresh,
+    Subscribe(TokenValue, Vec<String>),
+}
+
+/// state shared by all connections of the WebSocket (and HTTP) listener
+struct Shared {
+    tokens: Tokens<Client>,
+    snapshot: Snapshot,
+    requests: chan::Sender<ClientRequest>,
+    allowed_origins: Vec<String>,
+    auth: Arc<Auth>,
+    static_files: Option<StaticFiles>,
+    history: Arc<RwLock<History>>,
+    last_success: LastSuccess,
+    health_threshold: Duration,
+    pretty: bool,
+    /// keypair, if `wss://` is served
+    #[cfg(feature = "tls")]
+    tls: Option<openssl::ssl::SslAcceptor>,
+}
+
+/// Checks the `Origin` header (sent by browsers) against the allowlist.
+/// Clients which don't send one (i.e. non-browser clients) are allowed.
+fn origin_allowed(allowed: &[String], origin: Option<&str>) -> bool {
+    match origin {
+        _ if allowed.is_empty() => true,
+        None => true,
+        Some(origin) => allowed.iter().any(|i| i.eq_ignore_ascii_case(origin)),
+    }
+}
+
+/// Serializes `x` as response to `req`, gzip-compressed if the client accepts it.
+fn json_response<T: serde::Serialize>(req: &Request<()>, x: &T, pretty: bool) -> Response<Vec<u8>> {
+    let body = export::to_json(x, pretty);
+    let gz = gzip::accepted(http::header(req, "Accept-Encoding").map(str::as_bytes));
+    let mut res = if gz {
+        ok_response("application/json", gzip::compress(body.as_bytes()))
+    } else {
+        ok_response("application/json", body)
+    };
+    let headers = res.headers_mut();
+    if gz {
+        headers.insert("Content-Encoding", HeaderValue::from_static("gzip"));
+    }
+    headers.insert("Vary", HeaderValue::from_static("Accept-Encoding"));
+    res
+}
+
+/// Reports whether the topology was gathered successfully within `threshold`.
+fn health_response(since_success: Duration, threshold: Duration) -> Response<Vec<u8>> {
+    if since_success <= threshold {
+        ok_response("text/plain", "ok\n")
+    } else {
+        let body = format!(
+            "no successful gather within the last {}s\n",
+            since_success.as_secs()
+        );
+        http::text(StatusCode::SERVICE_UNAVAILABLE, body)
+    }
+}
+
+fn not_found() -> Response<Vec<u8>> {
+    http::text(StatusCode::NOT_FOUND, "not found\n")
+}
+
+fn ok_response(content_type: &str, body: impl Into<Vec<u8>>) -> Response<Vec<u8>> {
+    http::response(StatusCode::OK, content_type, body)
+}
+
+fn to_io(x: tungstenite::Error) -> io::Error {
+    match x {
+        tungstenite::Error::Io(x) => x,
+        x => io::Error::other(x.to_string()),
+    }
+}
+
+impl Shared {
+    /// Answers plain HTTP requests, returns `None` if `req` should be upgraded
+    /// to a WebSocket connection.
+    fn respond(&self, req: &Request<()>, peer: &str) -> Option<Response<Vec<u8>>> {
+        let get = req.method() == "GET";
+        // doesn't reveal anything about the topology, thus it doesn't require
+        // credentials (which e.g. liveness probes usually can't present)
+        if get && req.uri().path() == "/healthz" {
+            return Some(health_response(
+                self.last_success
+                    .read()
+                    .expect("last success lock poisoned")
+                    .elapsed(),
+                self.health_threshold,
+            ));
+        }
+        // applies to the WebSocket upgrade as well as to plain HTTP requests
+        if !self
+            .auth
+            .check(http::header(req, "Authorization").map(str::as_bytes))
+        {
+            warn!(
+                "Refusing request from {}: missing or invalid credentials",
+                peer
+            );
+            let mut res = http::text(StatusCode::UNAUTHORIZED, "unauthorized\n");
+            res.headers_mut().insert(
+                "WWW-Authenticate",
+                self.auth.challenge().parse().expect("invalid challenge"),
+            );
+            return Some(res);
+        }
+        if req.uri().path() == "/events" {
+            // streamed by `serve_sse`
+            if get {
+                return None;
+            }
+            let res = http::text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+            return Some(res);
+        }
+        // plain HTTP requests
+        if get && req.uri().path() == "/topology.json" {
+            return Some(
+                match &*self.snapshot.read().expect("snapshot lock poisoned") {
+                    Some(graph) => json_response(req, graph, self.pretty),
+                    None => http::text(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "no topology data available yet\n",
+                    ),
+                },
+            );
+        }
+        if get && req.uri().path() == "/version" {
+            return Some(json_response(req, &BUILD_INFO, self.pretty));
+        }
+        if get && req.uri().path() == "/history" {
+            let history = self.history.read().expect("history lock poisoned");
+            return Some(json_response(req, &history.list(), self.pretty));
+        }
+        if let Some(rev) = req.uri().path().strip_prefix("/history/") {
+            let history = self.history.read().expect("history lock poisoned");
+            return Some(match rev.parse().ok().and_then(|rev| history.get(rev)) {
+                Some(x) if get => json_response(req, x, self.pretty),
+                _ => not_found(),
+            });
+        }
+        // with static files, only `/ws` gets upgraded
+        match &self.static_files {
+            Some(sf) if req.uri().path() != "/ws" => {
+                let resource = req.uri().path_and_query().map_or("/", |x| x.as_str());
+                Some(match sf.get(resource) {
+                    Some((typ, data)) if get => ok_response(typ, data),
+                    None if get => not_found(),
+                    _ => http::text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n"),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Serves a single connection, i.e. a plain HTTP request or a WebSocket client.
+    fn serve(&self, stream: Stream) -> io::Result<()> {
+        let peer = stream.peer()?;
+        // don't let clients which never send a request occupy the thread
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        // the timeouts are set on the socket, thus they apply to TLS streams too
+        let socket = stream.try_clone()?;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let stream = tls
+                .accept(stream)
+                .map_err(|x| io::Error::other(x.to_string()))?;
+            return self.serve_stream(stream, &socket, &peer);
+        }
+        self.serve_stream(stream, &socket, &peer)
+    }
+
+    fn serve_stream<S: Read + Write>(
+        &self,
+        mut stream: S,
+        socket: &Stream,
+        peer: &str,
+    ) -> io::Result<()> {
+        let (req, rest) = http::read_request(&mut stream)?;
+        if let Some(res) = self.respond(&req, peer) {
+            return http::write_response(&mut stream, res);
+        }
+        if req.uri().path() == "/events" {
+            return self.serve_sse(stream, peer);
+        }
+        let res = match tungstenite::handshake::server::create_response(&req) {
+            Ok(x) => x,
+            Err(x) => {
+                let res = http::text(StatusCode::BAD_REQUEST, format!("{}\n", x));
+                return http::write_response(&mut stream, res);
+            }
+        };
+        let origin = http::header(&req, "Origin");
+        if !origin_allowed(&self.allowed_origins, origin) {
+            warn!(
+                "Refusing connection with {}: origin {} isn't allowed",
+                peer,
+                origin.unwrap_or_default()
+            );
+            let res = http::text(StatusCode::FORBIDDEN, "origin not allowed\n");
+            return http::write_response(&mut stream, res);
+        }
+        let (s_msg, r_msg) = chan::unbounded();
+        let tg = match self.tokens.try_acquire(Client::Ws(s_msg)) {
+            Ok(tg) => tg,
+            Err(_) => {
+                warn!("Refusing connection with {}: unable to acquire token", peer);
+                let res = http::text(StatusCode::SERVICE_UNAVAILABLE, "too many clients\n");
+                return http::write_response(&mut stream, res);
+            }
+        };
+        debug!(
+            "Connection with {} got token {} ({} active connections)",
+            peer,
+            tg.value(),
+            self.tokens.active()
+        );
+        // compressed if the client supports it
+        let deflate = deflate::negotiate(
+            req.headers()
+                .get_all("Sec-WebSocket-Extensions")
+                .iter()
+                .filter_map(|x| x.to_str().ok()),
+        );
+        let mut res = res;
+        if deflate {
+            res.headers_mut().insert(
+                "Sec-WebSocket-Extensions",
+                HeaderValue::from_static(deflate::RESPONSE),
+            );
+        }
+        // messages of the gather loop are queued until the connection runs
+        http::write_head(&mut stream, &res)?;
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(MAX_MESSAGE_LEN))
+            .max_frame_size(Some(MAX_MESSAGE_LEN));
+        // clients might stay silent for long, vanished ones are detected via pings
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let ret = if deflate {
+            let stream = Inflater::new(stream, rest, MAX_MESSAGE_LEN);
+            let mut ws = WebSocket::from_raw_socket(stream, Role::Server, Some(config));
+            self.run(&mut ws, &r_msg, tg.value(), true)
+        } else {
+            let mut ws = WebSocket::from_partially_read(stream, rest, Role::Server, Some(config));
+            self.run(&mut ws, &r_msg, tg.value(), false)
+        };
+        debug!("Connection with token {} closed", tg.value());
+        ret.map_err(to_io)
+    }
+
+    /// Streams the topology to a Server-Sent Events client.
+    fn serve_sse(&self, mut stream: impl Write, peer: &str) -> io::Result<()> {
+        let (s, r) = chan::unbounded();
+        let tg = match self.tokens.try_acquire(Client::Sse(s)) {
+            Ok(tg) => tg,
+            Err(_) => {
+                warn!("Refusing connection with {}: unable to acquire token", peer);
+                let res = http::text(StatusCode::SERVICE_UNAVAILABLE, "too many clients\n");
+                return http::write_response(&mut stream, res);
+            }
+        };
+        debug!("SSE connection with {} got token {}", peer, tg.value());
+        // runs until the client vanishes or the gather loop stops;
+        // dropping the token guard unregisters the client
+        let ret = sse::stream(&mut stream, r);
+        debug!("SSE connection with token {} closed", tg.value());
+        ret
+    }
+
+    /// Forwards the commands of a WebSocket client to the gather loop and
+    /// sends it the messages of the gather loop (`updates`), until the connection
+    /// gets closed. With `deflate`, text messages get sent compressed.
+    fn run<S: Read + Write>(
+        &self,
+        ws: &mut WebSocket<S>,
+        updates: &chan::Receiver<Message>,
+        token: TokenValue,
+        deflate: bool,
+    ) -> tungstenite::Result<()> {
+        loop {
+            // pings and the closing handshake are answered by tungstenite
+            let cmd = match ws.read() {
+                Ok(Message::Text(x)) => Some(serde_json::from_str(&x).map_err(|e| e.to_string())),
+                Ok(Message::Binary(_)) => Some(Err("binary messages aren't supported".to_string())),
+                Ok(_) => None,
+                Err(tungstenite::Error::Io(x))
+                    if matches!(
+                        x.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    None
+                }
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(x) => return Err(x),
+            };
+            // invalid messages don't close the connection
+            match cmd {
+                Some(Ok(ClientCommand::Refresh)) => {
+                    let _ = self.requests.send(ClientRequest::Refresh);
+                }
+                Some(Ok(ClientCommand::Subscribe { protos })) => {
+                    let _ = self.requests.send(ClientRequest::Subscribe(token, protos));
+                }
+                Some(Err(x)) => warn!("ignoring invalid message from client {}: {}", token, x),
+                None => {}
+            }
+            // the connection is closing, only the closing handshake remains
+            if !ws.can_write() {
+                continue;
+            }
+            loop {
+                match updates.try_recv() {
+                    Ok(Message::Text(x)) if deflate => ws.send(deflate::compress(&x))?,
+                    Ok(msg) => ws.send(msg)?,
+                    Err(chan::TryRecvError::Empty) => break,
+                    // the gather loop dropped the client, e.g. because of a shutdown
+                    Err(chan::TryRecvError::Disconnected) => {
+                        ws.close(Some(CloseFrame {
+                            code: CloseCode::Away,
+                            reason: "shutting down".into(),
+                        }))?;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// how the topology is built and presented to the clients
+struct ViewOptions {
+    parse_opts: ParseOptions,
+    /// merge parallel edges into one
+    aggregate_links: bool,
+    /// areas which are shown, empty = all
+    areas: Vec<String>,
+    labels: Labels,
+    resolver: Option<Resolver>,
+    group_names: BTreeMap<String, String>,
+    /// id or label of the local router
+    self_router: Option<String>,
+}
+
+impl ViewOptions {
+    /// Returns whether the self router was found (if there is one).
+    fn apply(&self, graph: &mut Graph) -> bool {
+        if !self.areas.is_empty() {
+            graph.retain_areas(&self.areas);
+        }
+        if self.aggregate_links {
+            graph.aggregate_parallel_edges();
+        }
+        graph.apply_labels(&self.labels);
+        // the static labels take precedence
+        if let Some(resolver) = &self.resolver {
+            graph.apply_labels(&resolver.labels(graph));
+        }
+        graph.rename_groups(&self.group_names);
+        match &self.self_router {
+            Some(id) => graph.mark_self(id),
+            None => true,
+        }
+    }
+
+    fn warn_self_missing(&self) {
+        if let Some(id) = &self.self_router {
+            warn!("self router {} not found", id);
+        }
+    }
+}
+
+/// Builds the graph from the outputs of the given protocols (or all).
+fn build_view(
+    outputs: &[(String, String)],
+    protos: Option<&[String]>,
+    opts: &ViewOptions,
+) -> Option<Graph> {
+    let outputs = outputs
+        .iter()
+        .filter(|(name, _)| protos.map(|p| p.contains(name)).unwrap_or(true))
+        .map(|(name, x)| (&name[..], &x[..]));
+    match gather::build_graph_named(outputs, &opts.parse_opts) {
+        Ok(mut graph) => {
+            // views of some protocols might lack the self router legitimately
+            if !opts.apply(&mut graph) && protos.is_none() {
+                opts.warn_self_missing();
+            }
+            Some(graph)
+        }
+        Err(x) => {
+            error!("unable to build topology of {:?}: {}", protos, x);
+            None
         }
     }
+}
+
+/// messages of the WebSocket and SSE streams are always compact
+fn to_json<T: serde::Serialize>(x: &T) -> String {
+    export::to_json(x, false)
+}
 
-    fn on_close(&mut self, code: ws::CloseCode, reason: &str) {
-        debug!("Connection closing due to ({:?}) {}", code, reason);
-        if let Handler::Running { tg } = std::mem::replace(self, Handler::Closed) {
-            std::mem::drop(tg);
+fn message<T: serde::Serialize>(typ: MessageType, stamp: Stamp, data: T) -> String {
+    to_json(&Envelope { typ, stamp, data })
+}
+
+/// Formats the whole graph as Server-Sent Event.
+fn sse_event(stamp: Stamp, graph: &Graph) -> String {
+    sse::event(&to_json(&Stamped { stamp, data: graph }))
+}
+
+/// Serializes the changes since the previous version of the graph,
+/// or the whole graph if there is no previous version.
+fn update_message(prev: Option<&Graph>, graph: &Graph, stamp: Stamp) -> Option<String> {
+    match prev {
+        Some(prev) => {
+            let patch = prev.diff(graph);
+            if patch.is_empty() {
+                None
+            } else {
+                Some(message(MessageType::Patch, stamp, &patch))
+            }
+        }
+        None => Some(message(MessageType::Snapshot, stamp, graph)),
+    }
+}
+
+/// Logs a summary of what changed since the previous version of the graph.
+fn log_change(prev: Option<&Graph>, graph: &Graph, stamp: Stamp, transitions: &[Transition]) {
+    let (nodes, edges) = prev.map_or((0, 0), |p| (p.nodes.len(), p.edges.len()));
+    let labels = |reachable| {
+        transitions
+            .iter()
+            .filter(|i| i.reachable == reachable)
+            .map(|i| &i.label[..])
+            .collect::<Vec<_>>()
+    };
+    info!(
+        rev = stamp.rev,
+        nodes = graph.nodes.len(),
+        nodes_delta = graph.nodes.len() as i64 - nodes as i64,
+        edges = graph.edges.len(),
+        edges_delta = graph.edges.len() as i64 - edges as i64,
+        became_unreachable = ?labels(false),
+        became_reachable = ?labels(true),
+        "topology changed"
+    );
+    for i in transitions {
+        if i.reachable {
+            info!(id = i.id, "{} became reachable", i.label);
         } else {
-            panic!("tried to close not running Handler object");
+            warn!(id = i.id, "{} became unreachable", i.label);
         }
     }
 }
 
 fn main() {
-    tracing_subscriber::fmt::init();
+    // log to stderr, so that stdout stays clean for the oneshot output
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    // checked beforehand, because the other options are irrelevant
+    if std::env::args().skip(1).any(|i| i == "--version") {
+        println!("{}", BUILD_INFO);
+        return;
+    }
+
+    let config = match config::Config::from_env() {
+        Ok(x) => x,
+        Err(x) => {
+            error!("{}", x);
+            std::process::exit(1);
+        }
+    };
+
+    let labels = match &config.labels {
+        Some(path) => match Labels::load(path) {
+            Ok(x) => x,
+            Err(x) => {
+                error!("{}", x);
+                std::process::exit(1);
+            }
+        },
+        None => Labels::default(),
+    };
+
+    let (s_req, r_req) = chan::unbounded();
+    let mut view_opts = ViewOptions {
+        parse_opts: config.parse_opts.clone(),
+        aggregate_links: config.aggregate_links,
+        areas: config.areas.clone(),
+        labels,
+        resolver: if config.resolve_dns && config.oneshot {
+            // there are no later updates, thus the names are needed right away
+            Some(Resolver::blocking())
+        } else if config.resolve_dns {
+            let s_req = s_req.clone();
+            // names which got resolved in the meantime are sent as an update
+            Some(Resolver::new(DNS_TTL, move || {
+                let _ = s_req.send(ClientRequest::Refresh);
+            }))
+        } else {
+            None
+        },
+        // the DOT export styles the nodes by their built-in groups
+        group_names: if config.oneshot && config.format == Format::Dot {
+            BTreeMap::new()
+        } else {
+            config.group_names.clone()
+        },
+        self_router: config.self_router.clone(),
+    };
+
+    if config.oneshot {
+        match gather::gather(&config.source, &config.parse_opts) {
+            Ok(mut graph) => {
+                if !view_opts.apply(&mut graph) {
+                    view_opts.warn_self_missing();
+                }
+                let mut out = graph.render(config.format, config.pretty);
+                if !config.format.is_binary() {
+                    out.push(b'\n');
+                }
+                if let Err(x) = std::io::Write::write_all(&mut std::io::stdout(), &out) {
+                    error!("unable to write output: {}", x);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            Err(x) => {
+                error!("gather failed: {}", x);
+                std::process::exit(1);
+            }
+        }
+    }
 
     let (s_tkinf, r_tkinf) = chan::unbounded();
-    let tokens = Tokens::new(s_tkinf);
+    let tokens: Tokens<Client> = Tokens::with_capacity(config.max_clients, s_tkinf);
+    let source = config.source.clone();
+    let interval = config.interval;
+    let ping_tick = chan::tick(config.ping_interval);
+    let mut debouncer = Debouncer::new(config.debounce);
+    let cache_ttl = config.cache_ttl;
+    let events = config.events;
+    let mut cache = gather::OutputCache::new(cache_ttl);
+    let snapshot: Snapshot = Default::default();
+    let history = Arc::new(RwLock::new(History::new(config.history)));
+    let history2 = Arc::clone(&history);
+    let last_success: LastSuccess = Arc::new(RwLock::new(Instant::now()));
+    let last_success2 = Arc::clone(&last_success);
+    let snapshot2 = Arc::clone(&snapshot);
 
-    spawn(move || {
-        use rand::prelude::*;
-        let mut prev_hash = None;
-        let mut senders: std::collections::BTreeMap<TokenValue, ws::Sender> = Default::default();
-        let mut rng = rand::thread_rng();
+    // a broken keypair is reported right away, not on the first connection
+    #[cfg(feature = "tls")]
+    let tls = match config
+        .tls
+        .as_ref()
+        .map(|x| tls::acceptor(&x.cert, &x.key))
+        .transpose()
+    {
+        Ok(x) => x,
+        Err(x) => {
+            error!("{}", x);
+            std::process::exit(1);
+        }
+    };
+    let shared = Arc::new(Shared {
+        tokens: tokens.clone(),
+        snapshot: Arc::clone(&snapshot),
+        requests: s_req.clone(),
+        allowed_origins: config.allowed_origins.clone(),
+        auth: Arc::new(Auth::new(&config.credentials)),
+        static_files: config.static_dir.clone().map(StaticFiles::new),
+        history: Arc::clone(&history),
+        last_success: Arc::clone(&last_success),
+        health_threshold: config.health_threshold,
+        pretty: config.pretty,
+        #[cfg(feature = "tls")]
+        tls,
+    });
+    let listener = match Listener::bind(&config.listen) {
+        Ok(x) => x,
+        Err(x) => {
+            error!(
+                "unable to bind WebSocket listener to {}: {}",
+                config.listen, x
+            );
+            std::process::exit(1);
+        }
+    };
+    spawn(move || loop {
+        match listener.accept() {
+            Ok(stream) => {
+                let shared = Arc::clone(&shared);
+                spawn(move || {
+                    if let Err(x) = shared.serve(stream) {
+                        debug!("connection failed: {}", x);
+                    }
+                });
+            }
+            Err(x) => warn!("unable to accept connection: {}", x),
+        }
+    });
+
+    let (s_quit, r_quit) = chan::bounded(1);
+    if let Err(x) = bird2topo::signal::on_termination(move || {
+        info!("shutting down");
+        // the gather loop drops all clients, which closes their connections
+        let _ = s_quit.send(());
+    }) {
+        warn!("unable to install signal handlers: {}", x);
+    }
+
+    let (s_reload, r_reload) = chan::unbounded();
+    if let Some(path) = config.labels.clone() {
+        if let Err(x) = bird2topo::signal::on_reload(move || match Labels::load(&path) {
+            Ok(x) => {
+                info!("reloaded labels from {}", path.display());
+                let _ = s_reload.send(x);
+            }
+            // keep the previous labels
+            Err(x) => error!("{}", x),
+        }) {
+            warn!("unable to install SIGHUP handler: {}", x);
+        }
+    }
+
+    let gather_thread = spawn(move || {
+        // the last sent versions of the topology, updates are sent as patches against them
+        let mut prev_graph: Option<Graph> = None;
+        let mut prev_views: BTreeMap<Vec<String>, Graph> = Default::default();
+        // version of the topology, all messages of the same version carry the same one
+        let mut stamp = Stamp::default();
+        let mut last_error: Option<String> = None;
+        let mut senders: BTreeMap<TokenValue, Client> = Default::default();
+        // protocols which the clients subscribed to (clients without entry get everything)
+        let mut filters: BTreeMap<TokenValue, Vec<String>> = Default::default();
+        let mut outputs = Vec::new();
+        // the view options changed (or a refresh was requested), thus the graph
+        // has to be rebuilt even if the outputs are unchanged
+        let mut rebuild = true;
+        let known_protos = source.names();
+        // while gathering fails, the attempts get less frequent
+        let mut backoff = Backoff::new(interval, MAX_BACKOFF);
+        // the group of unreachable nodes, as seen by the clients
+        let unreachable = view_opts
+            .group_names
+            .get(gather::group::UNREACHABLE)
+            .map_or(gather::group::UNREACHABLE, |x| &x[..])
+            .to_string();
         loop {
             let sel_start = Instant::now();
-            let mut timeout = chan::after(Duration::from_secs(10));
 
             // update data regulary
-            let mut got_update = false;
-            if let Some(dath) = gather::gather(OSPF_PROTOS) {
-                use std::hash::{Hash, Hasher};
-                let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                dath.hash(&mut hasher);
-                let new_hash = hasher.finish();
-                // only report update if hash mismatches
-                if std::mem::replace(&mut prev_hash, Some(new_hash)) != Some(new_hash) {
-                    if senders.is_empty() {
-                        // wait for new Handler to appear
-                        timeout = chan::never();
-                    } else {
-                        let mut ids: Vec<TokenValue> = senders.keys().copied().collect();
-                        ids.shuffle(&mut rng);
-                        // every websocket client gets the update
-                        senders
-                            .get_mut(&ids.pop().unwrap())
-                            .unwrap()
-                            .broadcast(dath)
-                            .expect("ws_sender.broadcast failed");
-                        got_update = true;
+            let gathered = cache.get(&source);
+            if gathered.is_ok() {
+                backoff.success();
+            }
+            let (graph, error) = match gathered {
+                // comparing the raw outputs is much cheaper than building the graph
+                // and comparing it, a pending change has to be observed again though
+                Ok(x) if x == outputs && !rebuild && !debouncer.is_pending() => {
+                    (None, last_error.clone())
+                }
+                Ok(x) => {
+                    outputs = x;
+                    rebuild = false;
+                    match build_view(&outputs, None, &view_opts) {
+                        Some(graph) => (Some(graph), None),
+                        None => (None, Some("unable to build topology".to_string())),
                     }
                 }
+                Err(x) => {
+                    backoff.failure();
+                    error!(
+                        "gather failed ({} times in a row, retrying in {:?}): {}",
+                        backoff.failures(),
+                        backoff.delay(),
+                        x
+                    );
+                    // the error gets cleared by the next successful build
+                    rebuild = true;
+                    (None, Some(x.to_string()))
+                }
+            };
+            let mut timeout = chan::after(backoff.delay());
+            if error.is_none() {
+                *last_success2.write().expect("last success lock poisoned") = Instant::now();
             }
-            if !senders.is_empty() && !got_update {
-                // ping everybody
-                for i in senders.values_mut() {
-                    i.ping(Vec::new()).expect("ws_sender.ping failed");
+            if error != last_error {
+                // the clients get told when gathering starts or stops failing
+                last_error = error;
+                if senders.values().any(Client::is_ws) {
+                    let status = Status::new(last_error.clone());
+                    let status = message(MessageType::Status, stamp, status);
+                    broadcast(&mut senders, &status, Client::is_ws);
                 }
             }
-
-            // don't loop too fast
-            while sel_start.elapsed() < Duration::from_millis(100) {
-                use crate::tokens::TokenUpdate;
+            let graph = graph.and_then(|graph| {
+                if prev_graph.as_ref() == Some(&graph) {
+                    // flapped back (if at all)
+                    debouncer.reset();
+                    None
+                } else {
+                    debouncer.update(Instant::now(), graph)
+                }
+            });
+            if debouncer.is_pending() {
+                // check soon whether the topology stabilized
+                // (cached outputs would look stable)
+                let check = [debouncer.window() / 2, cache_ttl, MIN_LOOP_TIME];
+                timeout = chan::after(check.iter().copied().max().unwrap());
+            }
+            if let Some(graph) = graph {
+                // only report update if something changed
+                let next = stamp.next();
+                if let Some(update) = update_message(prev_graph.as_ref(), &graph, next) {
+                    stamp = next;
+                    let transitions = prev_graph
+                        .as_ref()
+                        .map(|prev| prev.transitions(&graph, &unreachable))
+                        .unwrap_or_default();
+                    log_change(prev_graph.as_ref(), &graph, stamp, &transitions);
+                    // SSE clients get the whole graph every time
+                    let sse_update = if senders.values().any(Client::is_sse) {
+                        Some(sse_event(stamp, &graph))
+                    } else {
+                        None
+                    };
+                    if filters.is_empty() {
+                        broadcast(&mut senders, &update, Client::is_ws);
+                        if let Some(sse_update) = &sse_update {
+                            broadcast(&mut senders, sse_update, Client::is_sse);
+                        }
+                    } else {
+                        // clients with a filter get the updates of their own view of the topology
+                        let mut views: BTreeMap<&[String], Option<String>> = BTreeMap::new();
+                        senders.retain(|t, s| {
+                            let view_update = match filters.get(t) {
+                                _ if s.is_sse() => sse_update.clone(),
+                                Some(protos) => views
+                                    .entry(protos)
+                                    .or_insert_with(|| {
+                                        let view = build_view(&outputs, Some(protos), &view_opts)?;
+                                        // unchanged views get an empty patch, so that
+                                        // the revisions don't have gaps
+                                        let ret = update_message(
+                                            prev_views.get(&protos[..]),
+                                            &view,
+                                            stamp,
+                                        )
+                                        .unwrap_or_else(|| {
+                                            message(
+                                                MessageType::Patch,
+                                                stamp,
+                                                GraphPatch::default(),
+                                            )
+                                        });
+                                        prev_views.insert(protos.clone(), view);
+                                        Some(ret)
+                                    })
+                                    .clone(),
+                                None => Some(update.clone()),
+                            };
+                            match view_update.map(|x| s.send(x)) {
+                                Some(Err(x)) => {
+                                    warn!(
+                                        "unable to send update to client {}, dropping it: {}",
+                                        t, x
+                                    );
+                                    false
+                                }
+                                _ => true,
+                            }
+                        });
+                        prev_views.retain(|k, _| filters.values().any(|i| i == k));
+                    }
+                    if events {
+                        for i in &transitions {
+                            let event = message(MessageType::Event, stamp, i);
+                            broadcast(&mut senders, &event, Client::is_ws);
+                        }
+                    }
+                    // handlers (e.g. the HTTP endpoint) read the latest topology data from here
+                    *snapshot2.write().expect("snapshot lock poisoned") = Some(Stamped {
+                        stamp,
+                        data: graph.clone(),
+                    });
+                    history2
+                        .write()
+                        .expect("history lock poisoned")
+                        .push(stamp, graph.clone());
+                    prev_graph = Some(graph);
+                }
+            }
+            // wait for the next cycle, but don't loop too fast
+            let mut wake = false;
+            while !wake || sel_start.elapsed() < MIN_LOOP_TIME {
+                use bird2topo::tokens::TokenUpdate;
                 chan::select! {
                     recv(r_tkinf) -> tkinf => {
                         match tkinf {
                             Err(_) => break,
                             Ok(TokenUpdate::Acquire(t, s)) => {
-                                prev_hash = None;
-                                senders.insert(t, s);
+                                let mut res = match &prev_graph {
+                                    Some(graph) if s.is_sse() => s.send(sse_event(stamp, graph)),
+                                    Some(graph) => s.send(message(MessageType::Snapshot, stamp, graph)),
+                                    None => Ok(()),
+                                };
+                                if res.is_ok() && last_error.is_some() && s.is_ws() {
+                                    // the snapshot is outdated
+                                    let status = Status::new(last_error.clone());
+                                    res = s.send(message(MessageType::Status, stamp, status));
+                                }
+                                match res {
+                                    Ok(()) => {
+                                        senders.insert(t, s);
+                                        // gather again (as soon as allowed), because the snapshot
+                                        // might be up to an interval old
+                                        wake = true;
+                                    }
+                                    Err(x) => warn!("unable to send snapshot to client {}, dropping it: {}", t, x),
+                                }
                             },
                             Ok(TokenUpdate::Release(t)) => {
                                 senders.remove(&t);
+                                filters.remove(&t);
+                            },
+                        }
+                    },
+                    recv(r_req) -> req => {
+                        match req {
+                            Err(_) => break,
+                            Ok(ClientRequest::Refresh) => {
+                                // e.g. names were resolved in the meantime
+                                rebuild = true;
+                                wake = true;
+                            },
+                            Ok(ClientRequest::Subscribe(t, protos)) => {
+                                for i in protos.iter().filter(|i| !known_protos.contains(i)) {
+                                    warn!("client {} subscribed to unknown protocol {}", t, i);
+                                }
+                                if protos.is_empty() {
+                                    filters.remove(&t);
+                                } else {
+                                    filters.insert(t, protos);
+                                }
+                                // the client gets its new view of the topology right away
+                                let view = match (senders.get(&t), filters.get(&t)) {
+                                    (None, _) => None,
+                                    (Some(_), Some(protos)) => build_view(&outputs, Some(protos), &view_opts).map(|view| {
+                                        let ret = message(MessageType::Snapshot, stamp, &view);
+                                        prev_views.insert(protos.clone(), view);
+                                        ret
+                                    }),
+                                    (Some(_), None) => prev_graph.as_ref().map(|graph| message(MessageType::Snapshot, stamp, graph)),
+                                };
+                                if let Some(view) = view {
+                                    if let Err(x) = senders[&t].send(view) {
+                                        warn!("unable to send update to client {}, dropping it: {}", t, x);
+                                        senders.remove(&t);
+                                    }
+                                }
                             },
                         }
                     },
-                    recv(timeout) -> _ => {},
+                    recv(ping_tick) -> _ => {
+                        // ping everybody, clients which vanished in the meantime get dropped
+                        senders.retain(|t, s| match s.ping() {
+                            Ok(()) => true,
+                            Err(x) => {
+                                warn!("unable to ping client {}, dropping it: {}", t, x);
+                                false
+                            }
+                        });
+                    },
+                    recv(r_reload) -> new_labels => {
+                        if let Ok(new_labels) = new_labels {
+                            // the relabeled topology gets sent as an update
+                            view_opts.labels = new_labels;
+                            rebuild = true;
+                            wake = true;
+                        }
+                    },
+                    recv(r_quit) -> _ => return,
+                    recv(timeout) -> _ => wake = true,
+                }
+                if wake {
+                    // gather again as soon as allowed
+                    timeout = chan::after(MIN_LOOP_TIME.saturating_sub(sel_start.elapsed()));
                 }
             }
         }
     });
 
-    ws::listen("127.0.0.1:8942", |ws_sender| Handler::PreOpen {
-        tokens: tokens.clone(),
-        ws_sender,
-    })
-    .expect("unable to launch WebSocket listener");
+    if gather_thread.join().is_err() {
+        std::process::exit(1);
+    }
+    // wait (a bit) for the connections to close
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while tokens.active() > 0 && Instant::now() < deadline {
+        std::thread::sleep(MIN_LOOP_TIME);
+    }
+    if let Listen::Unix(path) = &config.listen {
+        if let Err(x) = std::fs::remove_file(path) {
+            warn!("unable to remove socket file {}: {}", path.display(), x);
+        }
+    }
 }
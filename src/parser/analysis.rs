@@ -0,0 +1,333 @@
+use super::{AreaData, EntryType, HashValue, Metric, Topology};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+/// Builds the directed adjacency list of the routers and transit networks
+/// of an area. Links to nodes without their own entry are left out (as are
+/// unreachable nodes, unless requested); stub networks and external routes
+/// aren't part of the graph.
+fn adjacency(
+    area: &AreaData<'_>,
+    with_unreachable: bool,
+) -> BTreeMap<HashValue, Vec<(HashValue, u32)>> {
+    let is_known = |id: &HashValue| {
+        area.routers
+            .get(id)
+            .map(|r| with_unreachable || !r.is_unreachable())
+            .unwrap_or(false)
+            || area
+                .networks
+                .get(id)
+                .map(|n| with_unreachable || !n.is_unreachable())
+                .unwrap_or(false)
+    };
+
+    let mut ret: BTreeMap<HashValue, Vec<(HashValue, u32)>> = BTreeMap::new();
+    for (&rid, router) in &area.routers {
+        if !with_unreachable && router.is_unreachable() {
+            continue;
+        }
+        let links = ret.entry(rid).or_default();
+        let neighbors = router
+            .neighbors()
+            .into_iter()
+            .chain(router.vlinks())
+            .map(|(i, id, metric)| (i, id, EntryType::Router, metric));
+        for (_, id, typ, metric) in neighbors.chain(router.conns()) {
+            match (typ, metric) {
+                (EntryType::Router, Metric::Internal(x))
+                | (EntryType::Network, Metric::Internal(x))
+                    if is_known(&id) =>
+                {
+                    links.push((id, x))
+                }
+                _ => {}
+            }
+        }
+    }
+    for (&nid, network) in &area.networks {
+        if !with_unreachable && network.is_unreachable() {
+            continue;
+        }
+        // the cost from a network to its attached routers is zero
+        let links = ret.entry(nid).or_default();
+        for &i in network.routers.iter().chain(std::iter::once(&network.dr)) {
+            if area.routers.contains_key(&i) && is_known(&i) {
+                links.push((i, 0));
+            }
+        }
+    }
+    ret
+}
+
+/// Dijkstra, returns the cost to reach each node from `root`.
+fn shortest_paths(
+    adj: &BTreeMap<HashValue, Vec<(HashValue, u32)>>,
+    root: HashValue,
+) -> BTreeMap<HashValue, u32> {
+    let mut ret = BTreeMap::new();
+    if !adj.contains_key(&root) {
+        return ret;
+    }
+
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((0u32, root)));
+    while let Some(Reverse((cost, id))) = queue.pop() {
+        if ret.contains_key(&id) {
+            // already reached via a cheaper path
+            continue;
+        }
+        ret.insert(id, cost);
+        for &(nid, metric) in adj.get(&id).into_iter().flatten() {
+            if !ret.contains_key(&nid) {
+                queue.push(Reverse((cost.saturating_add(metric), nid)));
+            }
+        }
+    }
+    ret
+}
+
+/// summary of a topology, e.g. for sanity checks against BIRD
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopologyStats {
+    pub areas: usize,
+    /// distinct routers (area border routers are counted once)
+    pub routers: usize,
+    /// distinct transit networks
+    pub networks: usize,
+    /// routers and networks which are unreachable in all of their areas
+    pub unreachable: usize,
+    /// longest shortest path between two reachable routers, per area
+    /// (areas without reachable routers are left out)
+    pub diameters: BTreeMap<String, u32>,
+}
+
+impl Topology<'_> {
+    /// Calculates the shortest path tree of the given area from `root`
+    /// (Dijkstra), and returns the cost to reach each reachable router
+    /// and transit network (including `root` itself with cost 0).
+    pub fn spf(&self, area: &str, root: HashValue) -> BTreeMap<HashValue, u32> {
+        match self.areas.get(area) {
+            Some(area) => shortest_paths(&adjacency(area, false), root),
+            None => BTreeMap::new(),
+        }
+    }
+
+    /// Calculates the longest shortest path between two reachable routers
+    /// of the given area.
+    pub fn diameter(&self, area: &str) -> Option<u32> {
+        let area = self.areas.get(area)?;
+        let adj = adjacency(area, false);
+        let routers: Vec<_> = area
+            .routers
+            .iter()
+            .filter(|(_, r)| !r.is_unreachable())
+            .map(|(&id, _)| id)
+            .collect();
+        routers
+            .iter()
+            .flat_map(|&root| {
+                let spf = shortest_paths(&adj, root);
+                routers
+                    .iter()
+                    .filter_map(|i| spf.get(i).copied())
+                    .collect::<Vec<_>>()
+            })
+            .max()
+    }
+
+    pub fn stats(&self) -> TopologyStats {
+        // id -> reachable in any area
+        let mut routers: BTreeMap<HashValue, bool> = BTreeMap::new();
+        let mut networks: BTreeMap<HashValue, bool> = BTreeMap::new();
+        for area in self.areas.values() {
+            for (&id, r) in &area.routers {
+                *routers.entry(id).or_default() |= !r.is_unreachable();
+            }
+            for (&id, n) in &area.networks {
+                *networks.entry(id).or_default() |= !n.is_unreachable();
+            }
+        }
+        TopologyStats {
+            areas: self.areas.len(),
+            routers: routers.len(),
+            networks: networks.len(),
+            unreachable: routers
+                .values()
+                .chain(networks.values())
+                .filter(|&&reachable| !reachable)
+                .count(),
+            diameters: self
+                .areas
+                .keys()
+                .filter_map(|&area| Some((area.to_string(), self.diameter(area)?)))
+                .collect(),
+        }
+    }
+
+    /// Splits the routers and transit networks of the given area into
+    /// connected components, largest first. Unreachable nodes are included,
+    /// because a partitioned area shows up as unreachable nodes
+    /// from the point of view of the local router.
+    pub fn components(&self, area: &str) -> Vec<BTreeSet<HashValue>> {
+        let adj = match self.areas.get(area) {
+            Some(area) => adjacency(area, true),
+            None => return Vec::new(),
+        };
+        // links are connections regardless of their direction
+        let mut undirected: BTreeMap<HashValue, BTreeSet<HashValue>> = BTreeMap::new();
+        for (&id, links) in &adj {
+            undirected.entry(id).or_default();
+            for &(nid, _) in links {
+                undirected.entry(id).or_default().insert(nid);
+                undirected.entry(nid).or_default().insert(id);
+            }
+        }
+
+        let mut ret = Vec::new();
+        let mut seen = BTreeSet::new();
+        for &start in undirected.keys() {
+            if seen.contains(&start) {
+                continue;
+            }
+            let mut comp = BTreeSet::new();
+            let mut stack = vec![start];
+            while let Some(id) = stack.pop() {
+                if comp.insert(id) {
+                    stack.extend(undirected[&id].iter().copied());
+                }
+            }
+            seen.extend(comp.iter().copied());
+            ret.push(comp);
+        }
+        ret.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse_topology, router2id};
+    use super::*;
+
+    //   A --1-- B --1-- D
+    //   |               |
+    //   +--5--  C --1---+
+    // plus E, which is unreachable
+    const DIAMOND: &str = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 1
+\t\trouter 10.0.0.3 metric 5
+\t\trouter 10.0.0.5 metric 1
+\trouter 10.0.0.2
+\t\tdistance 1
+\t\trouter 10.0.0.1 metric 1
+\t\trouter 10.0.0.4 metric 1
+\trouter 10.0.0.3
+\t\tdistance 3
+\t\trouter 10.0.0.1 metric 5
+\t\trouter 10.0.0.4 metric 1
+\trouter 10.0.0.4
+\t\tdistance 2
+\t\trouter 10.0.0.2 metric 1
+\t\trouter 10.0.0.3 metric 1
+\trouter 10.0.0.5
+\t\tunreachable
+\t\trouter 10.0.0.1 metric 1
+";
+
+    #[test]
+    fn test_spf_diamond() {
+        let topo = parse_topology(Topology::new(), DIAMOND).unwrap();
+        let spf = topo.spf("0.0.0.0", router2id("10.0.0.1"));
+        let expected: BTreeMap<_, _> = vec![
+            (router2id("10.0.0.1"), 0),
+            (router2id("10.0.0.2"), 1),
+            (router2id("10.0.0.3"), 3),
+            (router2id("10.0.0.4"), 2),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(spf, expected);
+
+        assert!(topo.spf("0.0.0.1", router2id("10.0.0.1")).is_empty());
+        assert!(topo.spf("0.0.0.0", router2id("10.0.0.5")).is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let topo = parse_topology(Topology::new(), DIAMOND).unwrap();
+        let stats = topo.stats();
+        assert_eq!(
+            stats,
+            TopologyStats {
+                areas: 1,
+                routers: 5,
+                networks: 0,
+                unreachable: 1,
+                // 10.0.0.1 -> 10.0.0.3 via 10.0.0.2 and 10.0.0.4
+                diameters: vec![("0.0.0.0".to_string(), 3)].into_iter().collect(),
+            }
+        );
+        assert_eq!(topo.diameter("0.0.0.1"), None);
+    }
+
+    #[test]
+    fn test_spf_transit_network() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tnetwork 10.0.0.0/24 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\tnetwork 10.0.0.0/24 metric 20
+\tnetwork 10.0.0.0/24
+\t\tdr 10.0.0.1
+\t\tdistance 10
+\t\trouter 10.0.0.1
+\t\trouter 10.0.0.2
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        let spf = topo.spf("0.0.0.0", router2id("10.0.0.2"));
+        assert_eq!(spf[&router2id("10.0.0.0/24")], 20);
+        assert_eq!(spf[&router2id("10.0.0.1")], 20);
+    }
+
+    #[test]
+    fn test_components_partitioned() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 1
+\trouter 10.0.0.2
+\t\tdistance 1
+\t\trouter 10.0.0.1 metric 1
+\trouter 10.0.0.3
+\t\tunreachable
+\t\trouter 10.0.0.4 metric 1
+\trouter 10.0.0.4
+\t\tunreachable
+\t\trouter 10.0.0.3 metric 1
+\trouter 10.0.0.5
+\t\tunreachable
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        let set = |ids: &[&str]| ids.iter().map(|i| router2id(i)).collect::<BTreeSet<_>>();
+        let mut comps = topo.components("0.0.0.0");
+        assert_eq!(comps.len(), 3);
+        assert_eq!(comps.pop(), Some(set(&["10.0.0.5"])));
+        comps.sort();
+        let mut expected = vec![
+            set(&["10.0.0.1", "10.0.0.2"]),
+            set(&["10.0.0.3", "10.0.0.4"]),
+        ];
+        expected.sort();
+        assert_eq!(comps, expected);
+
+        assert!(topo.components("0.0.0.1").is_empty());
+    }
+}
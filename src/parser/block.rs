@@ -0,0 +1,401 @@
+#[derive(Debug)]
+struct RawBlock<'a> {
+    /// visual width of the indentation
+    indent: usize,
+    head: &'a str,
+    subs: Vec<RawBlock<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Block<S> {
+    pub head: S,
+    pub subs: Vec<Block<S>>,
+}
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum BlockParseError {
+    #[error("line {0} is dedented to a level which doesn't match any enclosing block")]
+    InconsistentIndent(usize),
+
+    #[error("line {0} exceeds the maximum nesting depth")]
+    TooDeep(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockOptions {
+    /// a tab advances the indentation to the next multiple of this width
+    pub tab_width: usize,
+    /// maximum number of nested levels, which bounds the recursion
+    /// when the blocks are finished (and dropped)
+    pub max_depth: usize,
+    /// lines which start with this prefix (after the indentation) are skipped
+    pub comment_prefix: Option<String>,
+}
+
+impl Default for BlockOptions {
+    fn default() -> Self {
+        BlockOptions {
+            tab_width: 8,
+            max_depth: 32,
+            comment_prefix: None,
+        }
+    }
+}
+
+impl<'a> RawBlock<'a> {
+    fn finish(self) -> Block<&'a str> {
+        let RawBlock { head, subs, .. } = self;
+        Block {
+            head,
+            subs: subs.into_iter().map(RawBlock::finish).collect(),
+        }
+    }
+}
+
+/// This function splits a string into the indention (spaces before text) and the real text
+fn get_indent(s: &str) -> (&str, &str) {
+    let mut ibc = 0;
+    for x in s.chars() {
+        if x.is_whitespace() {
+            ibc += x.len_utf8();
+        } else {
+            break;
+        }
+    }
+    s.split_at(ibc)
+}
+
+/// This function calculates the visual width of an indentation,
+/// which makes it possible to compare indentations with mixed tabs and spaces
+fn indent_width(indent: &str, tab_width: usize) -> usize {
+    indent.chars().fold(0, |w, x| {
+        if x == '\t' && tab_width != 0 {
+            (w / tab_width + 1) * tab_width
+        } else {
+            w + 1
+        }
+    })
+}
+
+struct Parser<'a> {
+    base: Vec<RawBlock<'a>>,
+    stack: Vec<RawBlock<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn pop_scope_while(&mut self, mut cond: impl FnMut(&RawBlock<'a>) -> bool) {
+        while !self.stack.is_empty() && cond(self.stack.last().unwrap()) {
+            // merge block with parent
+            self.merge_prev();
+        }
+    }
+
+    fn top_indent(&self) -> usize {
+        self.stack.last().map(|top| top.indent).unwrap_or(0)
+    }
+
+    fn merge_prev(&mut self) {
+        if let Some(old_top) = self.stack.pop() {
+            if let Some(top2) = self.stack.last_mut() {
+                top2.subs.push(old_top);
+            } else {
+                self.base.push(old_top);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<Block<&'a str>> {
+        self.pop_scope_while(|_| true);
+        assert!(self.stack.is_empty());
+        self.base.into_iter().map(RawBlock::finish).collect()
+    }
+}
+
+pub fn parse_nested_blocks(s: &str) -> Result<Vec<Block<&str>>, BlockParseError> {
+    parse_nested_blocks_with(s, &BlockOptions::default())
+}
+
+pub fn parse_nested_blocks_with<'a>(
+    s: &'a str,
+    opts: &BlockOptions,
+) -> Result<Vec<Block<&'a str>>, BlockParseError> {
+    let mut parser = Parser {
+        base: vec![],
+        stack: vec![],
+    };
+
+    for (lineno, i) in s
+        .lines()
+        .map(get_indent)
+        .enumerate()
+        .filter(|&(_, (_, i))| !i.is_empty())
+        .filter(|&(_, (_, i))| match &opts.comment_prefix {
+            Some(pfx) => !i.starts_with(&pfx[..]),
+            None => true,
+        })
+        .map(|(lineno, (indent, head))| {
+            (
+                lineno + 1,
+                RawBlock {
+                    indent: indent_width(indent, opts.tab_width),
+                    head,
+                    subs: Vec::new(),
+                },
+            )
+        })
+    {
+        // reduce scope if necessary
+        let depth = parser.stack.len();
+        parser.pop_scope_while(|top| top.indent > i.indent);
+        if parser.stack.len() != depth && i.indent != parser.top_indent() {
+            // we left at least one block, but didn't arrive at the level of
+            // an enclosing block (e.g. because of mixed tabs and spaces)
+            return Err(BlockParseError::InconsistentIndent(lineno));
+        }
+
+        if i.indent == parser.top_indent() {
+            // same level of indention -> same block
+            parser.merge_prev();
+        } else {
+            // part of block $top
+            // do nothing
+        }
+        if parser.stack.len() >= opts.max_depth {
+            return Err(BlockParseError::TooDeep(lineno));
+        }
+        parser.stack.push(i);
+    }
+
+    Ok(parser.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_indent() {
+        assert_eq!(("  	  ", "abc"), get_indent("  	  abc"));
+    }
+
+    #[test]
+    fn test_indent_width() {
+        assert_eq!(indent_width("", 8), 0);
+        assert_eq!(indent_width("    ", 8), 4);
+        assert_eq!(indent_width("\t", 8), 8);
+        assert_eq!(indent_width("  \t", 8), 8);
+        assert_eq!(indent_width("\t  ", 4), 6);
+    }
+
+    #[test]
+    fn test_parse_nbs0() {
+        assert_eq!(parse_nested_blocks(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_parse_nbs1() {
+        assert_eq!(
+            parse_nested_blocks("a"),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![]
+            }])
+        );
+        assert_eq!(
+            parse_nested_blocks("a\n"),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![]
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parse_nbs2() {
+        assert_eq!(
+            parse_nested_blocks("a\nb"),
+            Ok(vec![
+                Block {
+                    head: "a",
+                    subs: vec![]
+                },
+                Block {
+                    head: "b",
+                    subs: vec![]
+                }
+            ])
+        );
+        assert_eq!(
+            parse_nested_blocks("a\n\r\nb\n"),
+            Ok(vec![
+                Block {
+                    head: "a",
+                    subs: vec![]
+                },
+                Block {
+                    head: "b",
+                    subs: vec![]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_nbs3() {
+        assert_eq!(
+            parse_nested_blocks("a\n  b"),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![Block {
+                    head: "b",
+                    subs: vec![]
+                }]
+            }])
+        );
+        assert_eq!(
+            parse_nested_blocks("a\n\r\n  b\n"),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![Block {
+                    head: "b",
+                    subs: vec![]
+                }]
+            }])
+        );
+        assert_eq!(
+            parse_nested_blocks("a\n\r\n  b\nc"),
+            Ok(vec![
+                Block {
+                    head: "a",
+                    subs: vec![Block {
+                        head: "b",
+                        subs: vec![]
+                    }]
+                },
+                Block {
+                    head: "c",
+                    subs: vec![]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_nbs_inconsistent() {
+        assert_eq!(
+            parse_nested_blocks("a\n    b\n  c"),
+            Err(BlockParseError::InconsistentIndent(3))
+        );
+        assert_eq!(
+            parse_nested_blocks("a\n\tb\n\n  c\n"),
+            Err(BlockParseError::InconsistentIndent(4))
+        );
+    }
+
+    #[test]
+    fn test_parse_nbs_comments() {
+        let s = "# saved on r1\na\n  # b follows\n  b\n# c\nc";
+        let opts = BlockOptions {
+            comment_prefix: Some("#".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_nested_blocks_with(s, &opts),
+            Ok(vec![
+                Block {
+                    head: "a",
+                    subs: vec![Block {
+                        head: "b",
+                        subs: vec![]
+                    }]
+                },
+                Block {
+                    head: "c",
+                    subs: vec![]
+                }
+            ])
+        );
+        assert_eq!(parse_nested_blocks(s).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_parse_nbs_too_deep() {
+        let opts = BlockOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        assert!(parse_nested_blocks_with("a\n b\n c\nd\n e", &opts).is_ok());
+        assert_eq!(
+            parse_nested_blocks_with("a\n b\n  c", &opts),
+            Err(BlockParseError::TooDeep(3))
+        );
+
+        // deeply nested input would overflow the stack otherwise
+        let s: String = (0..1000).map(|i| format!("{}x\n", " ".repeat(i))).collect();
+        assert_eq!(parse_nested_blocks(&s), Err(BlockParseError::TooDeep(33)));
+    }
+
+    #[test]
+    fn test_parse_nbs_mixed() {
+        // siblings
+        assert_eq!(
+            parse_nested_blocks("a\n\tb\n        c"),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![
+                    Block {
+                        head: "b",
+                        subs: vec![]
+                    },
+                    Block {
+                        head: "c",
+                        subs: vec![]
+                    }
+                ]
+            }])
+        );
+        // children
+        assert_eq!(
+            parse_nested_blocks("a\n    b\n\tc\n  \td"),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![Block {
+                    head: "b",
+                    subs: vec![
+                        Block {
+                            head: "c",
+                            subs: vec![]
+                        },
+                        Block {
+                            head: "d",
+                            subs: vec![]
+                        }
+                    ]
+                }]
+            }])
+        );
+        // custom tab width
+        assert_eq!(
+            parse_nested_blocks_with(
+                "a\n\tb\n    c",
+                &BlockOptions {
+                    tab_width: 4,
+                    ..Default::default()
+                }
+            ),
+            Ok(vec![Block {
+                head: "a",
+                subs: vec![
+                    Block {
+                        head: "b",
+                        subs: vec![]
+                    },
+                    Block {
+                        head: "c",
+                        subs: vec![]
+                    }
+                ]
+            }])
+        );
+    }
+}
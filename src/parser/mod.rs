@@ -1,9 +1,15 @@
+mod analysis;
+mod block;
+
+pub use analysis::TopologyStats;
+pub use block::{parse_nested_blocks, parse_nested_blocks_with, BlockOptions, BlockParseError};
 use serde_json::{map::Map, Value};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
+use tracing::{debug, warn};
 
-type Distance = u8;
-type HashValue = u64;
+pub type Distance = u8;
+pub type HashValue = u64;
 
 #[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Ord, Eq)]
 pub enum EntryType {
@@ -13,12 +19,13 @@ pub enum EntryType {
     Network,
     XNetwork,
     XRouter,
+    VLink,
 }
 
 #[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Ord, Eq)]
 pub enum Metric {
-    Internal(u16),
-    External(u16),
+    Internal(u32),
+    External(u32),
 }
 
 impl fmt::Display for Metric {
@@ -34,13 +41,16 @@ impl fmt::Display for Metric {
 pub struct Entry<'a> {
     pub typ: EntryType,
     pub obj: &'a str,
+    /// id of `obj` (see [`router2id`]), calculated once while parsing
+    pub id: HashValue,
     pub metric: Metric,
+    pub tag: Option<u32>,
 }
 
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum EntryParseError {
-    #[error("invalid entry type")]
-    InvalidEntryType,
+    #[error("{0}")]
+    InvalidEntryType(#[from] UnknownEntryType),
 
     #[error("invalid metric value")]
     InvalidMetric(#[from] std::num::ParseIntError),
@@ -48,14 +58,24 @@ pub enum EntryParseError {
     #[error("unknown metric")]
     UnknownMetric,
 
+    #[error("invalid tag value")]
+    InvalidTag(std::num::ParseIntError),
+
+    #[error("unknown entry attribute")]
+    UnknownAttribute,
+
     #[error("entry with invalid structure (elements = {0})")]
     InvalidStructure(usize),
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("unknown entry type '{0}'")]
+pub struct UnknownEntryType(pub String);
+
 impl std::str::FromStr for EntryType {
-    type Err = ();
+    type Err = UnknownEntryType;
 
-    fn from_str(s: &str) -> Result<Self, ()> {
+    fn from_str(s: &str) -> Result<Self, UnknownEntryType> {
         Ok(match s {
             "external" => EntryType::External,
             "router" => EntryType::Router,
@@ -63,14 +83,57 @@ impl std::str::FromStr for EntryType {
             "network" => EntryType::Network,
             "xnetwork" => EntryType::XNetwork,
             "xrouter" => EntryType::XRouter,
-            _ => return Err(()),
+            "vlink" => EntryType::VLink,
+            _ => return Err(UnknownEntryType(s.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EntryType::External => "external",
+            EntryType::Router => "router",
+            EntryType::StubNet => "stubnet",
+            EntryType::Network => "network",
+            EntryType::XNetwork => "xnetwork",
+            EntryType::XRouter => "xrouter",
+            EntryType::VLink => "vlink",
         })
     }
 }
 
+impl EntryType {
+    /// key of the entries of this type in the node details
+    /// (same as the `Debug` output)
+    pub fn details_key(self) -> &'static str {
+        match self {
+            EntryType::External => "External",
+            EntryType::Router => "Router",
+            EntryType::StubNet => "StubNet",
+            EntryType::Network => "Network",
+            EntryType::XNetwork => "XNetwork",
+            EntryType::XRouter => "XRouter",
+            EntryType::VLink => "VLink",
+        }
+    }
+}
+
 impl Metric {
+    /// the raw cost, regardless of its kind
+    pub fn value(&self) -> u32 {
+        match *self {
+            Metric::Internal(x) | Metric::External(x) => x,
+        }
+    }
+
+    /// whether this is a type 2 external metric (`metric2`)
+    pub fn is_external(&self) -> bool {
+        matches!(self, Metric::External(_))
+    }
+
     fn new(t: &str, v: &str) -> Result<Metric, EntryParseError> {
-        let v: u16 = v.parse()?;
+        let v: u32 = v.parse()?;
         match t {
             "metric" => Ok(Metric::Internal(v)),
             "metric2" => Ok(Metric::External(v)),
@@ -79,22 +142,62 @@ impl Metric {
     }
 }
 
+fn parse_tag(t: &str, v: &str) -> Result<u32, EntryParseError> {
+    if t != "tag" {
+        return Err(EntryParseError::UnknownAttribute);
+    }
+    match try_eat_pfx(v, "0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => v.parse(),
+    }
+    .map_err(EntryParseError::InvalidTag)
+}
+
 impl<'a> Entry<'a> {
     fn from_str(s: &'a str) -> Result<Self, EntryParseError> {
-        let parts: Vec<_> = s.split_ascii_whitespace().collect();
-        if parts.len() != 4 {
-            return Err(EntryParseError::InvalidStructure(parts.len()));
+        let mut parts: Vec<_> = s.split_ascii_whitespace().collect();
+        if parts.starts_with(&["virtual", "link"]) {
+            // BIRD prints virtual links as `virtual link <id> metric <m>`
+            parts.remove(0);
+            parts[0] = "vlink";
         }
+        let tag = match parts.len() {
+            4 => None,
+            6 => Some(parse_tag(parts[4], parts[5])?),
+            n => return Err(EntryParseError::InvalidStructure(n)),
+        };
         Ok(Entry {
-            typ: parts[0]
-                .parse()
-                .map_err(|()| EntryParseError::InvalidEntryType)?,
+            typ: parts[0].parse()?,
             obj: parts[1],
+            id: router2id(parts[1]),
             metric: Metric::new(parts[2], parts[3])?,
+            tag,
         })
     }
 }
 
+impl Entry<'_> {
+    /// Returns the type of an external route (1 or 2), which depends on the
+    /// metric (`metric` or `metric2`), or `None` if this isn't an external route.
+    pub fn external_type(&self) -> Option<u8> {
+        match (self.typ, self.metric) {
+            (EntryType::External, Metric::Internal(_)) => Some(1),
+            (EntryType::External, Metric::External(_)) => Some(2),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.typ, self.obj, self.metric)?;
+        if let Some(tag) = self.tag {
+            write!(f, " tag 0x{:08x}", tag)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub struct RouterData<'a> {
     distance: Distance,
@@ -102,49 +205,66 @@ pub struct RouterData<'a> {
 }
 
 impl<'a> RouterData<'a> {
+    pub fn distance(&self) -> Distance {
+        self.distance
+    }
+    pub fn entries(&self) -> &[Entry<'a>] {
+        &self.entries[..]
+    }
     pub fn get_details(&self) -> Map<String, Value> {
         let mut ret = Map::new();
         ret.insert("distance".to_string(), Value::Number(self.distance.into()));
-        for i in self.entries.iter() {
+        // the entries are sorted by type, thus each type
+        // (usually) needs only a single lookup
+        for run in self.entries.chunk_by(|a, b| a.typ == b.typ) {
             if let Value::Array(ref mut a) = ret
-                .entry(format!("{:?}", i.typ))
-                .or_insert_with(|| Value::Array(Vec::new()))
+                .entry(run[0].typ.details_key())
+                .or_insert_with(|| Value::Array(Vec::with_capacity(run.len())))
             {
-                a.push(Value::String(format!("{} {}", i.obj, i.metric)));
+                a.extend(run.iter().map(|i| {
+                    use fmt::Write;
+                    // "<obj> metric2 <u32> E2 tag 0x<8 digits>"
+                    let mut x = String::with_capacity(i.obj.len() + 38);
+                    let _ = write!(x, "{} {}", i.obj, i.metric);
+                    if let Some(t) = i.external_type() {
+                        let _ = write!(x, " E{}", t);
+                    }
+                    if let Some(tag) = i.tag {
+                        let _ = write!(x, " tag 0x{:08x}", tag);
+                    }
+                    Value::String(x)
+                }));
             }
         }
         ret
     }
-    pub fn neighbors(&self) -> Vec<(&'a str, u16)> {
+    pub fn neighbors(&self) -> Vec<(&'a str, HashValue, Metric)> {
         self.entries
             .iter()
             .filter_map(|i| {
                 if i.typ == EntryType::Router {
-                    Some((
-                        i.obj,
-                        match i.metric {
-                            Metric::Internal(x) => x,
-                            Metric::External(x) => 1000 + x,
-                        },
-                    ))
+                    Some((i.obj, i.id, i.metric))
                 } else {
                     None
                 }
             })
             .collect()
     }
-    pub fn conns(&self) -> Vec<(&'a str, u16)> {
+    /// virtual links (to other routers, through a transit area)
+    pub fn vlinks(&self) -> Vec<(&'a str, HashValue, Metric)> {
+        self.entries
+            .iter()
+            .filter(|i| i.typ == EntryType::VLink)
+            .map(|i| (i.obj, i.id, i.metric))
+            .collect()
+    }
+    /// all links to non-routers, together with the entry type which created them
+    pub fn conns(&self) -> Vec<(&'a str, HashValue, EntryType, Metric)> {
         self.entries
             .iter()
             .filter_map(|i| {
-                if i.typ != EntryType::Router {
-                    Some((
-                        i.obj,
-                        match i.metric {
-                            Metric::Internal(x) => x,
-                            Metric::External(x) => 1000 + x,
-                        },
-                    ))
+                if i.typ != EntryType::Router && i.typ != EntryType::VLink {
+                    Some((i.obj, i.id, i.typ, i.metric))
                 } else {
                     None
                 }
@@ -156,6 +276,7 @@ impl<'a> RouterData<'a> {
     }
 }
 
+#[derive(Clone)]
 pub struct NetworkData {
     pub distance: Distance,
     pub dr: HashValue,
@@ -168,15 +289,18 @@ impl NetworkData {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct AreaData<'a> {
     pub routers: BTreeMap<HashValue, RouterData<'a>>,
     pub networks: BTreeMap<HashValue, NetworkData>,
 }
 
+#[derive(Clone)]
 pub struct Topology<'a> {
     pub interned: BTreeMap<HashValue, &'a str>,
     pub areas: HashMap<&'a str, AreaData<'a>>,
+    /// BIRD version from the first parsed banner (e.g. `2.0.7`)
+    pub bird_version: Option<String>,
 }
 
 impl Topology<'_> {
@@ -184,7 +308,119 @@ impl Topology<'_> {
         Topology {
             interned: BTreeMap::new(),
             areas: HashMap::new(),
+            bird_version: None,
+        }
+    }
+}
+
+/// Dumps the parsed topology in the format of `birdc show ospf state all`
+/// (areas, routers and networks sorted by name), for troubleshooting.
+impl fmt::Display for Topology<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |id: &HashValue| -> String {
+            match self.interned.get(id) {
+                Some(x) => x.to_string(),
+                None => format!("#{:016x}", id),
+            }
+        };
+        let distance = |f: &mut fmt::Formatter<'_>, d: Distance| {
+            if d == 255 {
+                writeln!(f, "\t\tunreachable")
+            } else {
+                writeln!(f, "\t\tdistance {}", d)
+            }
+        };
+
+        if let Some(version) = &self.bird_version {
+            writeln!(f, "BIRD v{} ready.", version)?;
+        }
+        let mut areas: Vec<_> = self.areas.iter().collect();
+        areas.sort_by_key(|(name, _)| *name);
+        for (area_name, area) in areas {
+            writeln!(f, "area {}", area_name)?;
+
+            let mut routers: Vec<_> = area.routers.iter().map(|(k, v)| (name(k), v)).collect();
+            routers.sort_by(|a, b| a.0.cmp(&b.0));
+            for (router_name, router) in routers {
+                writeln!(f, "\trouter {}", router_name)?;
+                distance(f, router.distance)?;
+                for ent in &router.entries {
+                    writeln!(f, "\t\t{}", ent)?;
+                }
+            }
+
+            let mut networks: Vec<_> = area.networks.iter().map(|(k, v)| (name(k), v)).collect();
+            networks.sort_by(|a, b| a.0.cmp(&b.0));
+            for (network_name, network) in networks {
+                writeln!(f, "\tnetwork {}", network_name)?;
+                if network.dr != 0 {
+                    writeln!(f, "\t\tdr {}", name(&network.dr))?;
+                }
+                distance(f, network.distance)?;
+                let mut members: Vec<_> = network.routers.iter().map(name).collect();
+                members.sort();
+                for member in members {
+                    writeln!(f, "\t\trouter {}", member)?;
+                }
+            }
         }
+        Ok(())
+    }
+}
+
+impl<'a> Topology<'a> {
+    /// Merges another topology (e.g. of another protocol) into this one.
+    ///
+    /// Unlike parsing both into the same topology, differing distances
+    /// aren't an error (e.g. the OSPFv2 and OSPFv3 instances of a dual-stack
+    /// router calculate their shortest paths independently), the shorter
+    /// one is kept. The DR of a network is kept, if it is already known.
+    pub fn merge(&mut self, other: Topology<'a>) -> Result<(), TopologyParseError<'a>> {
+        for (id, name) in &other.interned {
+            match self.interned.get(id) {
+                Some(&known) if known != *name => {
+                    return Err(TopologyParseError::HashCollision { a: known, b: name })
+                }
+                _ => {}
+            }
+        }
+        check_targets(&other.areas, &self.interned)?;
+        check_targets(&self.areas, &other.interned)?;
+        let Topology {
+            interned,
+            areas,
+            bird_version,
+        } = other;
+        self.interned.extend(interned);
+        if self.bird_version.is_none() {
+            self.bird_version = bird_version;
+        }
+        for (area_name, area) in areas {
+            let dst = self.areas.entry(area_name).or_default();
+            for (rid, router) in area.routers {
+                let rdat = dst.routers.entry(rid).or_insert_with(|| RouterData {
+                    distance: 255,
+                    entries: Vec::new(),
+                });
+                rdat.distance = rdat.distance.min(router.distance);
+                rdat.entries.extend(router.entries);
+                rdat.entries.sort();
+                rdat.entries.dedup();
+            }
+            for (nid, network) in area.networks {
+                let ndat = dst.networks.entry(nid).or_insert_with(|| NetworkData {
+                    distance: 255,
+                    dr: 0,
+                    routers: Default::default(),
+                });
+                ndat.distance = ndat.distance.min(network.distance);
+                if ndat.dr == 0 {
+                    ndat.dr = network.dr;
+                }
+                ndat.routers.extend(network.routers);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -196,19 +432,30 @@ pub enum TopologyParseError<'a> {
     #[error("invalid distance value")]
     InvalidDistance(#[from] std::num::ParseIntError),
 
+    #[error("invalid block structure: {0}")]
+    InvalidBlocks(#[from] BlockParseError),
+
     #[error("unknown topology structure (level {0})")]
     UnknownStructure(u32),
 
     #[error("attempt to merge topologies with mismatching distance values (old = {0}, new = {1})")]
     DistanceMismatch(u8, u8),
+
+    #[error("hash collision between '{a}' and '{b}'")]
+    HashCollision { a: &'a str, b: &'a str },
 }
 
+/// Maps a router (or network) name to its id.
+///
+/// This uses 64-bit FNV-1a, because the ids are sent to the clients and
+/// thus must be stable across builds and platforms
+/// (unlike the output of `DefaultHasher`).
 pub fn router2id(router: &str) -> HashValue {
-    use std::hash::{Hash, Hasher};
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    router.hash(&mut hasher);
-    let h = hasher.finish();
-    h
+    const FNV_OFFSET_BASIS: HashValue = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: HashValue = 0x0000_0100_0000_01b3;
+    router.bytes().fold(FNV_OFFSET_BASIS, |h, b| {
+        (h ^ HashValue::from(b)).wrapping_mul(FNV_PRIME)
+    })
 }
 
 fn try_eat_pfx<'a>(s: &'a str, pfx: &str) -> Option<&'a str> {
@@ -219,28 +466,134 @@ fn try_eat_pfx<'a>(s: &'a str, pfx: &str) -> Option<&'a str> {
     }
 }
 
+/// what to do if a router or network is seen with different distances
+/// (e.g. in concatenated outputs, or successive LSAs)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DistancePolicy {
+    /// fail with [`TopologyParseError::DistanceMismatch`]
+    #[default]
+    Strict,
+    /// keep the shorter distance
+    Min,
+}
+
+impl std::str::FromStr for DistancePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(match s {
+            "strict" => DistancePolicy::Strict,
+            "min" => DistancePolicy::Min,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// skip (and log) unparsable entries instead of failing,
+    /// e.g. for entry types introduced by newer BIRD versions;
+    /// this also drops edges to unknown nodes when building the graph
+    /// (see `Graph::check_edges`), which such entries can cause
+    pub lenient: bool,
+    pub distance_policy: DistancePolicy,
+    /// skip lines which start with this prefix, e.g. in annotated saved outputs
+    pub comment_prefix: Option<String>,
+}
+
+/// Updates the distance of a router or network, `old` is 255
+/// if it wasn't seen yet (or is unreachable).
+fn update_distance<'a>(
+    old: &mut Distance,
+    new: Distance,
+    policy: DistancePolicy,
+) -> Result<(), TopologyParseError<'a>> {
+    *old = match policy {
+        _ if *old == new || *old == 255 => new,
+        DistancePolicy::Strict => return Err(TopologyParseError::DistanceMismatch(*old, new)),
+        DistancePolicy::Min => new.min(*old),
+    };
+    Ok(())
+}
+
+/// Checks that the targets of the entries (e.g. stubnets) don't collide
+/// with (differently named) routers and networks.
+fn check_targets<'a>(
+    areas: &HashMap<&'a str, AreaData<'a>>,
+    interned: &BTreeMap<HashValue, &'a str>,
+) -> Result<(), TopologyParseError<'a>> {
+    let entries = areas
+        .values()
+        .flat_map(|area| area.routers.values())
+        .flat_map(|router| router.entries.iter());
+    for i in entries {
+        match interned.get(&i.id) {
+            Some(&known) if known != i.obj => {
+                return Err(TopologyParseError::HashCollision { a: known, b: i.obj })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 pub fn parse_topology<'a, 'b: 'a>(
     base_topo: Topology<'b>,
     s: &'a str,
+) -> Result<Topology<'a>, TopologyParseError<'a>> {
+    parse_topology_with(base_topo, s, &ParseOptions::default())
+}
+
+pub fn parse_topology_with<'a, 'b: 'a>(
+    base_topo: Topology<'b>,
+    s: &'a str,
+    opts: &ParseOptions,
 ) -> Result<Topology<'a>, TopologyParseError<'a>> {
     static AREA_PFX: &str = "area ";
+    static BANNER_PFX: &str = "BIRD v";
 
-    let mut blocks_ = indented_blocks::parse_nested_blocks(s);
-    if blocks_.is_empty() || !blocks_.remove(0).head.starts_with("BIRD v") {
-        return Err(TopologyParseError::UnknownStructure(0));
+    let block_opts = BlockOptions {
+        comment_prefix: opts.comment_prefix.clone(),
+        ..Default::default()
+    };
+    let mut blocks_ = parse_nested_blocks_with(s, &block_opts)?;
+    // some birdc setups print e.g. a welcome line before the banner
+    let start = match blocks_.iter().position(|b| b.head.starts_with(BANNER_PFX)) {
+        Some(x) => x,
+        None => return Err(TopologyParseError::UnknownStructure(0)),
+    };
+    for i in &blocks_[..start] {
+        debug!("skipping line before the BIRD banner: {}", i.head);
     }
+    let banner = &blocks_[start].head[BANNER_PFX.len()..];
+    blocks_.drain(..=start);
 
     let Topology {
         mut interned,
         mut areas,
+        mut bird_version,
     } = base_topo;
-    let mut intern = |router: &'a str| -> HashValue {
+    if bird_version.is_none() {
+        bird_version = banner.split_ascii_whitespace().next().map(str::to_string);
+    }
+    let mut intern = |router: &'a str| -> Result<HashValue, TopologyParseError<'a>> {
         let h = router2id(router);
-        interned.entry(h).or_insert(router);
-        h
+        let known = *interned.entry(h).or_insert(router);
+        if known != router {
+            return Err(TopologyParseError::HashCollision {
+                a: known,
+                b: router,
+            });
+        }
+        Ok(h)
     };
 
     for area in blocks_ {
+        if area.head.starts_with(BANNER_PFX) {
+            // start of another (concatenated) output, the areas of it
+            // get merged like those of separately parsed outputs
+            continue;
+        }
         if !area.head.starts_with(AREA_PFX) {
             return Err(TopologyParseError::UnknownStructure(1));
         }
@@ -250,7 +603,7 @@ pub fn parse_topology<'a, 'b: 'a>(
         for areaelem in &area.subs {
             let xsubs = &areaelem.subs;
             if let Some(router_name) = try_eat_pfx(areaelem.head, "router ") {
-                let rid = intern(router_name);
+                let rid = intern(router_name)?;
                 let mut rdat = areadat.routers.entry(rid).or_insert_with(|| RouterData {
                     distance: 255,
                     entries: Vec::new(),
@@ -261,33 +614,29 @@ pub fn parse_topology<'a, 'b: 'a>(
                         return Err(TopologyParseError::UnknownStructure(3));
                     }
                     if ent.head == "unreachable" {
-                        let new_distance: u8 = 255;
-                        if rdat.distance != new_distance && rdat.distance != 255 {
-                            return Err(TopologyParseError::DistanceMismatch(
-                                rdat.distance,
-                                new_distance,
-                            ));
-                        }
-                        rdat.distance = new_distance;
+                        update_distance(&mut rdat.distance, 255, opts.distance_policy)?;
                     } else if let Some(distance) = try_eat_pfx(ent.head, "distance ") {
-                        let new_distance: u8 = distance.parse()?;
-                        if rdat.distance != new_distance && rdat.distance != 255 {
-                            return Err(TopologyParseError::DistanceMismatch(
-                                rdat.distance,
-                                new_distance,
-                            ));
-                        }
-                        rdat.distance = new_distance;
+                        update_distance(
+                            &mut rdat.distance,
+                            distance.parse()?,
+                            opts.distance_policy,
+                        )?;
                     } else {
-                        rdat.entries.push(Entry::from_str(ent.head).map_err(|err| {
-                            TopologyParseError::InvalidEntry { ent: ent.head, err }
-                        })?);
+                        match Entry::from_str(ent.head) {
+                            Ok(x) => rdat.entries.push(x),
+                            Err(err) if opts.lenient => {
+                                warn!("skipping invalid entry ({}): {}", err, ent.head);
+                            }
+                            Err(err) => {
+                                return Err(TopologyParseError::InvalidEntry { ent: ent.head, err })
+                            }
+                        }
                     }
                 }
                 rdat.entries.sort();
                 rdat.entries.dedup();
             } else if let Some(network_name) = try_eat_pfx(areaelem.head, "network ") {
-                let nid = intern(network_name);
+                let nid = intern(network_name)?;
                 let mut ndat = areadat.networks.entry(nid).or_insert_with(|| NetworkData {
                     distance: 255,
                     dr: 0,
@@ -298,27 +647,17 @@ pub fn parse_topology<'a, 'b: 'a>(
                         return Err(TopologyParseError::UnknownStructure(3));
                     }
                     if ent.head == "unreachable" {
-                        let new_distance: u8 = 255;
-                        if ndat.distance != new_distance && ndat.distance != 255 {
-                            return Err(TopologyParseError::DistanceMismatch(
-                                ndat.distance,
-                                new_distance,
-                            ));
-                        }
-                        ndat.distance = new_distance;
+                        update_distance(&mut ndat.distance, 255, opts.distance_policy)?;
                     } else if let Some(distance) = try_eat_pfx(ent.head, "distance ") {
-                        let new_distance: u8 = distance.parse()?;
-                        if ndat.distance != new_distance && ndat.distance != 255 {
-                            return Err(TopologyParseError::DistanceMismatch(
-                                ndat.distance,
-                                new_distance,
-                            ));
-                        }
-                        ndat.distance = new_distance;
+                        update_distance(
+                            &mut ndat.distance,
+                            distance.parse()?,
+                            opts.distance_policy,
+                        )?;
                     } else if let Some(dr) = try_eat_pfx(ent.head, "dr ") {
-                        ndat.dr = intern(dr);
+                        ndat.dr = intern(dr)?;
                     } else if let Some(router) = try_eat_pfx(ent.head, "router ") {
-                        ndat.routers.insert(intern(router));
+                        ndat.routers.insert(intern(router)?);
                     }
                 }
             } else {
@@ -327,5 +666,420 @@ pub fn parse_topology<'a, 'b: 'a>(
         }
     }
 
-    Ok(Topology { interned, areas })
+    check_targets(&areas, &interned)?;
+    Ok(Topology {
+        interned,
+        areas,
+        bird_version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiple_banners() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tstubnet 10.0.1.0/24 metric 10
+BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tstubnet 2001:db8::/64 metric 10
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        assert_eq!(topo.bird_version.as_deref(), Some("2.0.7"));
+        assert_eq!(topo.areas.len(), 1);
+        let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.1")];
+        assert_eq!(
+            router.conns(),
+            vec![
+                (
+                    "10.0.1.0/24",
+                    router2id("10.0.1.0/24"),
+                    EntryType::StubNet,
+                    Metric::Internal(10)
+                ),
+                (
+                    "2001:db8::/64",
+                    router2id("2001:db8::/64"),
+                    EntryType::StubNet,
+                    Metric::Internal(10)
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_router2id() {
+        // reference values of 64-bit FNV-1a, the ids must never change
+        assert_eq!(router2id(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(router2id("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(router2id("10.0.0.1"), 0x87f7_ef5d_e06f_f78d);
+    }
+
+    #[test]
+    fn test_display() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.1
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\tnetwork 10.0.2.0/24 metric 10
+\t\texternal 0.0.0.0/0 metric2 10000 tag 0x00000001
+\trouter 10.0.0.3
+\t\tunreachable
+\tnetwork 10.0.2.0/24
+\t\tdr 10.0.0.2
+\t\tdistance 20
+\t\trouter 10.0.0.3
+\t\trouter 10.0.0.2
+area 0.0.0.0
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tstubnet 10.0.1.0/24 metric 10
+\t\trouter 10.0.0.2 metric 10
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        let dump = topo.to_string();
+        assert_eq!(
+            dump,
+            "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\t\tstubnet 10.0.1.0/24 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+area 0.0.0.1
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\texternal 0.0.0.0/0 metric2 10000 tag 0x00000001
+\t\tnetwork 10.0.2.0/24 metric 10
+\trouter 10.0.0.3
+\t\tunreachable
+\tnetwork 10.0.2.0/24
+\t\tdr 10.0.0.2
+\t\tdistance 20
+\t\trouter 10.0.0.2
+\t\trouter 10.0.0.3
+"
+        );
+
+        // the dump can be parsed again
+        let again = parse_topology(Topology::new(), &dump).unwrap();
+        assert_eq!(again.to_string(), dump);
+    }
+
+    #[test]
+    fn test_merge() {
+        let v4 = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tstubnet 10.0.1.0/24 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+";
+        let v6 = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tstubnet 2001:db8::/64 metric 10
+\trouter 10.0.0.2
+\t\tdistance 20
+";
+        // the same router with different distances is an error within one
+        // instance, but not between instances
+        assert!(matches!(
+            parse_topology(parse_topology(Topology::new(), v4).unwrap(), v6),
+            Err(TopologyParseError::DistanceMismatch(10, 20))
+        ));
+        let opts = ParseOptions {
+            distance_policy: DistancePolicy::Min,
+            ..Default::default()
+        };
+        let topo = parse_topology(Topology::new(), v4).unwrap();
+        let topo = parse_topology_with(topo, v6, &opts).unwrap();
+        let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.2")];
+        assert_eq!(router.distance(), 10);
+        // an unreachable router stays reachable
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.2
+\t\tunreachable
+";
+        let topo = parse_topology_with(topo, s, &opts).unwrap();
+        let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.2")];
+        assert_eq!(router.distance(), 10);
+
+        let mut topo = parse_topology(Topology::new(), v4).unwrap();
+        topo.merge(parse_topology(Topology::new(), v6).unwrap())
+            .unwrap();
+        let area = &topo.areas["0.0.0.0"];
+        assert_eq!(area.routers[&router2id("10.0.0.2")].distance(), 10);
+        assert_eq!(area.routers[&router2id("10.0.0.1")].conns().len(), 2);
+    }
+
+    #[test]
+    fn test_hash_collision() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+";
+        // a real collision is infeasible to find, thus it is faked
+        let mut seeded = Topology::new();
+        seeded.interned.insert(router2id("10.0.0.1"), "10.0.0.99");
+
+        assert!(matches!(
+            parse_topology(seeded.clone(), s),
+            Err(TopologyParseError::HashCollision {
+                a: "10.0.0.99",
+                b: "10.0.0.1"
+            })
+        ));
+
+        let mut topo = seeded;
+        assert!(matches!(
+            topo.merge(parse_topology(Topology::new(), s).unwrap()),
+            Err(TopologyParseError::HashCollision {
+                a: "10.0.0.99",
+                b: "10.0.0.1"
+            })
+        ));
+        // targets of entries (e.g. stubnets) collide as well
+        let stubnet = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.2
+\t\tdistance 0
+\t\tstubnet 10.0.1.0/24 metric 10
+";
+        let mut seeded = Topology::new();
+        seeded
+            .interned
+            .insert(router2id("10.0.1.0/24"), "10.0.0.99");
+        assert!(matches!(
+            parse_topology(seeded.clone(), stubnet),
+            Err(TopologyParseError::HashCollision {
+                a: "10.0.0.99",
+                b: "10.0.1.0/24"
+            })
+        ));
+        let mut topo = seeded;
+        assert!(matches!(
+            topo.merge(parse_topology(Topology::new(), stubnet).unwrap()),
+            Err(TopologyParseError::HashCollision {
+                a: "10.0.0.99",
+                b: "10.0.1.0/24"
+            })
+        ));
+
+        // the same name isn't a collision
+        let mut topo = parse_topology(Topology::new(), s).unwrap();
+        topo.merge(parse_topology(Topology::new(), s).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_metric() {
+        assert_eq!(Metric::Internal(10).value(), 10);
+        assert_eq!(Metric::External(16_777_215).value(), 16_777_215);
+        assert!(!Metric::Internal(10).is_external());
+        assert!(Metric::External(10).is_external());
+
+        // type 2 external metrics have 24 bits
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\texternal 10.9.0.0/16 metric2 16777215
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.1")];
+        assert_eq!(
+            router.conns(),
+            vec![(
+                "10.9.0.0/16",
+                router2id("10.9.0.0/16"),
+                EntryType::External,
+                Metric::External(16_777_215)
+            )]
+        );
+        assert!(topo
+            .to_string()
+            .contains("\t\texternal 10.9.0.0/16 metric2 16777215\n"));
+    }
+
+    #[test]
+    fn test_external_type() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\texternal 0.0.0.0/0 metric2 10000 tag 0x00000064
+\t\texternal 10.1.0.0/16 metric 20
+\t\tstubnet 10.0.1.0/24 metric 20
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.1")];
+        let types: Vec<_> = router
+            .entries()
+            .iter()
+            .map(|i| (i.obj, i.external_type()))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("0.0.0.0/0", Some(2)),
+                ("10.1.0.0/16", Some(1)),
+                ("10.0.1.0/24", None)
+            ]
+        );
+        let details = router.get_details();
+        assert_eq!(
+            details["External"],
+            serde_json::json!([
+                "0.0.0.0/0 metric2 10000 E2 tag 0x00000064",
+                "10.1.0.0/16 metric 20 E1"
+            ])
+        );
+        assert_eq!(
+            details["StubNet"],
+            serde_json::json!(["10.0.1.0/24 metric 20"])
+        );
+    }
+
+    #[test]
+    fn test_tag() {
+        let tag = |s| Entry::from_str(s).map(|i| i.tag);
+        assert_eq!(tag("external 10.0.0.0/8 metric2 20").unwrap(), None);
+        assert_eq!(
+            tag("external 10.0.0.0/8 metric2 20 tag 0x00000064").unwrap(),
+            Some(100)
+        );
+        assert_eq!(
+            tag("external 10.0.0.0/8 metric2 20 tag 100").unwrap(),
+            Some(100)
+        );
+        assert!(matches!(
+            tag("external 10.0.0.0/8 metric2 20 tag 0xzz"),
+            Err(EntryParseError::InvalidTag(_))
+        ));
+        assert!(matches!(
+            tag("external 10.0.0.0/8 metric2 20 tag -1"),
+            Err(EntryParseError::InvalidTag(_))
+        ));
+        assert!(matches!(
+            tag("external 10.0.0.0/8 metric2 20 color 1"),
+            Err(EntryParseError::UnknownAttribute)
+        ));
+        assert!(matches!(
+            tag("external 10.0.0.0/8 metric2 20 tag"),
+            Err(EntryParseError::InvalidStructure(5))
+        ));
+
+        // a malformed tag fails the whole (strict) parse
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\texternal 10.0.0.0/8 metric2 20 tag 0xzz
+";
+        assert!(matches!(
+            parse_topology(Topology::new(), s),
+            Err(TopologyParseError::InvalidEntry { .. })
+        ));
+    }
+
+    #[test]
+    fn test_vlink() {
+        for ent in &[
+            "vlink 10.0.0.9 metric 30",
+            "virtual link 10.0.0.9 metric 30",
+        ] {
+            let s = format!(
+                "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\t{}
+\t\tstubnet 10.0.1.0/24 metric 10
+",
+                ent
+            );
+            let topo = parse_topology(Topology::new(), &s).unwrap();
+            let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.1")];
+            assert_eq!(
+                router.vlinks(),
+                vec![("10.0.0.9", router2id("10.0.0.9"), Metric::Internal(30))]
+            );
+            assert!(router.neighbors().is_empty());
+            assert_eq!(router.conns().len(), 1);
+            assert!(topo.to_string().contains("\t\tvlink 10.0.0.9 metric 30\n"));
+        }
+    }
+
+    #[test]
+    fn test_preamble() {
+        let s = "Connected to BIRD socket /run/bird/bird.ctl
+BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+";
+        let topo = parse_topology(Topology::new(), s).unwrap();
+        assert_eq!(topo.bird_version.as_deref(), Some("2.0.7"));
+        assert_eq!(topo.areas["0.0.0.0"].routers.len(), 1);
+
+        assert!(matches!(
+            parse_topology(Topology::new(), "junk\narea 0.0.0.0\n"),
+            Err(TopologyParseError::UnknownStructure(0))
+        ));
+    }
+
+    #[test]
+    fn test_lenient() {
+        let s = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\tfancylink 10.0.0.2 metric 10
+\t\tstubnet 10.0.1.0/24 metric 10
+";
+        match parse_topology(Topology::new(), s) {
+            Err(x @ TopologyParseError::InvalidEntry { .. }) => assert_eq!(
+                x.to_string(),
+                "invalid entry (unknown entry type 'fancylink'): fancylink 10.0.0.2 metric 10"
+            ),
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        assert_eq!(
+            "fancylink".parse::<EntryType>(),
+            Err(UnknownEntryType("fancylink".to_string()))
+        );
+        let opts = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let topo = parse_topology_with(Topology::new(), s, &opts).unwrap();
+        let router = &topo.areas["0.0.0.0"].routers[&router2id("10.0.0.1")];
+        assert_eq!(
+            router.conns(),
+            vec![(
+                "10.0.1.0/24",
+                router2id("10.0.1.0/24"),
+                EntryType::StubNet,
+                Metric::Internal(10)
+            )]
+        );
+    }
 }
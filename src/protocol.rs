@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+
+/// commands which WebSocket clients can send to the server
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum ClientCommand {
+    /// gather the topology again right away
+    Refresh,
+    /// only receive the topology of the given protocols
+    /// (an empty list resets the filter)
+    Subscribe { protos: Vec<String> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageType {
+    /// the whole topology (`Graph`)
+    Snapshot,
+    /// changes of the topology (`GraphPatch`)
+    Patch,
+    /// state of the server (`Status`)
+    Status,
+    /// a node became reachable or unreachable (`Transition`)
+    Event,
+}
+
+/// identifies a version of the topology
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stamp {
+    /// revision of the topology, which is incremented on every change
+    /// (thus a gap means that a patch was missed)
+    pub rev: u64,
+    /// when this revision was gathered (Unix time in milliseconds)
+    pub generated_at: u64,
+}
+
+impl Stamp {
+    /// the next revision, gathered now
+    pub fn next(&self) -> Stamp {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        Stamp {
+            rev: self.rev + 1,
+            generated_at: now.as_millis() as u64,
+        }
+    }
+}
+
+/// envelope of all messages which the server sends to the WebSocket clients
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    #[serde(rename = "type")]
+    pub typ: MessageType,
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    pub data: T,
+}
+
+/// a topology together with its version (e.g. for `/topology.json`)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stamped<T> {
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    /// the topology is up to date
+    Ok,
+    /// gathering the topology fails, the last revision is outdated
+    Stale,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub state: State,
+    /// why the topology couldn't be gathered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Status {
+    pub fn new(error: Option<String>) -> Status {
+        Status {
+            state: if error.is_some() {
+                State::Stale
+            } else {
+                State::Ok
+            },
+            reason: error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            serde_json::from_str::<ClientCommand>(r#"{"cmd":"refresh"}"#).unwrap(),
+            ClientCommand::Refresh
+        );
+        assert_eq!(
+            serde_json::from_str::<ClientCommand>(r#"{"cmd":"subscribe","protos":["ospf6"]}"#)
+                .unwrap(),
+            ClientCommand::Subscribe {
+                protos: vec!["ospf6".to_string()]
+            }
+        );
+        assert!(serde_json::from_str::<ClientCommand>(r#"{"cmd":"reboot"}"#).is_err());
+        assert!(serde_json::from_str::<ClientCommand>(r#"{"cmd":"subscribe"}"#).is_err());
+    }
+
+    #[test]
+    fn test_envelope() {
+        let stamp = Stamp {
+            rev: 42,
+            generated_at: 1_600_000_000_000,
+        };
+        let msg = Envelope {
+            typ: MessageType::Status,
+            stamp,
+            data: Status::new(Some("birdc failed".to_string())),
+        };
+        assert_eq!(
+            serde_json::to_value(&msg).unwrap(),
+            serde_json::json!({
+                "type": "status",
+                "rev": 42,
+                "generated_at": 1_600_000_000_000u64,
+                "data": { "state": "stale", "reason": "birdc failed" }
+            })
+        );
+        let graph = crate::Graph {
+            bird_version: None,
+            edges: vec![],
+            nodes: vec![],
+            schema_version: 1,
+        };
+        assert_eq!(
+            serde_json::to_value(&Stamped { stamp, data: graph }).unwrap(),
+            serde_json::json!({
+                "rev": 42,
+                "generated_at": 1_600_000_000_000u64,
+                "bird_version": null,
+                "edges": [],
+                "nodes": [],
+                "schema_version": 1
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&Status::new(None)).unwrap(),
+            serde_json::json!({ "state": "ok" })
+        );
+    }
+
+    #[test]
+    fn test_event() {
+        use crate::gather::{build_graph, group};
+        let up = "BIRD v2.0.7 ready.
+area 0.0.0.0
+\trouter 10.0.0.1
+\t\tdistance 0
+\t\trouter 10.0.0.2 metric 10
+\trouter 10.0.0.2
+\t\tdistance 10
+\t\trouter 10.0.0.1 metric 10
+";
+        let down = up.replace("distance 10", "unreachable");
+        let graphs: Vec<_> = [up, &down, up]
+            .iter()
+            .map(|i| build_graph(&[i.to_string()], &Default::default()).unwrap())
+            .collect();
+        let events: Vec<_> = graphs
+            .windows(2)
+            .flat_map(|w| w[0].transitions(&w[1], group::UNREACHABLE))
+            .map(|data| Envelope {
+                typ: MessageType::Event,
+                stamp: Stamp::default(),
+                data,
+            })
+            .collect();
+        let id = crate::parser::router2id("10.0.0.2");
+        assert_eq!(
+            serde_json::to_value(&events).unwrap(),
+            serde_json::json!([
+                {
+                    "type": "event", "rev": 0, "generated_at": 0,
+                    "data": { "id": id, "label": "10.0.0.2", "reachable": false }
+                },
+                {
+                    "type": "event", "rev": 0, "generated_at": 0,
+                    "data": { "id": id, "label": "10.0.0.2", "reachable": true }
+                }
+            ])
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::gather::Graph;
+use crate::labels::Labels;
+use crossbeam_channel as chan;
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Resolves router ids to names via reverse DNS.
+///
+/// Lookups never block (unless created via [`Resolver::blocking`]): uncached
+/// (or expired) ids are resolved by a separate thread, which calls `on_update`
+/// once a name changed.
+#[derive(Clone)]
+pub struct Resolver {
+    inner: Arc<Mutex<Inner>>,
+    /// `None` if the lookups are resolved right away
+    queue: Option<chan::Sender<Ipv4Addr>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// resolved names (or `None` if there is no PTR record), and when they were resolved
+    cache: HashMap<Ipv4Addr, (Instant, Option<String>)>,
+    /// ids which are queued for resolution
+    pending: HashSet<Ipv4Addr>,
+}
+
+fn reverse_lookup(ip: Ipv4Addr) -> Option<String> {
+    unsafe {
+        let mut addr: libc::sockaddr_in = std::mem::zeroed();
+        addr.sin_family = libc::AF_INET as libc::sa_family_t;
+        addr.sin_addr.s_addr = u32::from(ip).to_be();
+        let mut host = [0 as libc::c_char; 1025];
+        let ret = libc::getnameinfo(
+            &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        );
+        if ret != 0 {
+            return None;
+        }
+        std::ffi::CStr::from_ptr(host.as_ptr())
+            .to_str()
+            .ok()
+            .map(str::to_string)
+    }
+}
+
+impl Resolver {
+    /// Starts the resolver thread, resolved names are cached for `ttl`.
+    pub fn new(ttl: Duration, on_update: impl Fn() + Send + 'static) -> Resolver {
+        let (queue, r_queue) = chan::unbounded::<Ipv4Addr>();
+        let inner = Arc::new(Mutex::new(Inner::default()));
+        let inner2 = Arc::clone(&inner);
+        std::thread::spawn(move || {
+            for ip in r_queue {
+                let name = reverse_lookup(ip);
+                debug!("resolve: {} -> {:?}", ip, name);
+                let changed = {
+                    let mut inner = inner2.lock().expect("resolver cache poisoned");
+                    inner.pending.remove(&ip);
+                    let old = inner.cache.insert(ip, (Instant::now() + ttl, name.clone()));
+                    old.map(|(_, x)| x) != Some(name)
+                };
+                if changed {
+                    on_update();
+                }
+            }
+        });
+        Resolver {
+            inner,
+            queue: Some(queue),
+        }
+    }
+
+    /// Creates a resolver whose lookups block until the name is resolved,
+    /// without any caching (e.g. for a single export).
+    pub fn blocking() -> Resolver {
+        Resolver {
+            inner: Default::default(),
+            queue: None,
+        }
+    }
+
+    /// Returns the cached name of `ip` (even if it is expired),
+    /// and queues the resolution if it isn't cached or expired.
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<String> {
+        let queue = match &self.queue {
+            Some(x) => x,
+            None => return reverse_lookup(ip),
+        };
+        let mut inner = self.inner.lock().expect("resolver cache poisoned");
+        let (expired, ret) = match inner.cache.get(&ip) {
+            Some((until, name)) => (Instant::now() >= *until, name.clone()),
+            None => (true, None),
+        };
+        if expired && inner.pending.insert(ip) {
+            let _ = queue.send(ip);
+        }
+        ret
+    }
+
+    /// Looks up the nodes whose label is a router id (and wasn't
+    /// already replaced), returns the names which are known yet.
+    pub fn labels(&self, graph: &Graph) -> Labels {
+        graph
+            .nodes
+            .iter()
+            .filter(|i| !i.details.contains_key("raw_id"))
+            .filter_map(|i| {
+                let ip = i.label.parse().ok()?;
+                Some((i.label.clone(), self.lookup(ip)?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached() {
+        // without the resolver thread, the queue can be inspected
+        let (queue, r_queue) = chan::unbounded();
+        let resolver = Resolver {
+            inner: Default::default(),
+            queue: Some(queue),
+        };
+        let ip = Ipv4Addr::new(192, 0, 2, 1);
+        resolver.inner.lock().unwrap().cache.insert(
+            ip,
+            (
+                Instant::now() + Duration::from_secs(60),
+                Some("r1.example.com".to_string()),
+            ),
+        );
+        assert_eq!(resolver.lookup(ip).as_deref(), Some("r1.example.com"));
+        assert!(r_queue.try_recv().is_err());
+
+        // uncached ids get queued, but only once
+        let other = Ipv4Addr::new(192, 0, 2, 2);
+        assert_eq!(resolver.lookup(other), None);
+        assert_eq!(resolver.lookup(other), None);
+        assert_eq!(r_queue.try_recv(), Ok(other));
+        assert!(r_queue.try_recv().is_err());
+        assert!(resolver.inner.lock().unwrap().pending.contains(&other));
+
+        // expired names are still returned, while being resolved again
+        resolver
+            .inner
+            .lock()
+            .unwrap()
+            .cache
+            .insert(ip, (Instant::now(), Some("r1.example.com".to_string())));
+        assert_eq!(resolver.lookup(ip).as_deref(), Some("r1.example.com"));
+        assert_eq!(r_queue.try_recv(), Ok(ip));
+    }
+}
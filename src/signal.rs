@@ -0,0 +1,129 @@
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// write ends of the self-pipes
+static TERM_WR: AtomicI32 = AtomicI32::new(-1);
+static RELOAD_WR: AtomicI32 = AtomicI32::new(-1);
+
+fn notify(pipe: &AtomicI32) {
+    let fd = pipe.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // SAFETY: `write` is async-signal-safe and the buffer outlives the call.
+        // The write end is non-blocking, thus a full pipe (i.e. a wakeup is
+        // already pending) makes this fail instead of hanging the handler.
+        unsafe {
+            libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+extern "C" fn handle_term(_: libc::c_int) {
+    notify(&TERM_WR);
+}
+
+extern "C" fn handle_reload(_: libc::c_int) {
+    notify(&RELOAD_WR);
+}
+
+fn install(
+    sig: libc::c_int,
+    handler: extern "C" fn(libc::c_int),
+    flags: libc::c_int,
+) -> io::Result<()> {
+    // SAFETY: `sigaction` is valid when zeroed, and gets fully initialized here.
+    // The handlers only call `notify`, which only uses async-signal-safe
+    // functions and atomics (no allocations or locks).
+    unsafe {
+        let mut act: libc::sigaction = std::mem::zeroed();
+        act.sa_sigaction = handler as *const () as usize;
+        act.sa_flags = libc::SA_RESTART | flags;
+        libc::sigemptyset(&mut act.sa_mask);
+        if libc::sigaction(sig, &act, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Creates a self-pipe, returns its read end.
+fn pipe(wr: &AtomicI32) -> io::Result<RawFd> {
+    let mut fds: [RawFd; 2] = [-1; 2];
+    // SAFETY: `fds` has room for the two descriptors
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the write end was just created and is owned by us
+    if unsafe { libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    wr.store(fds[1], Ordering::Relaxed);
+    Ok(fds[0])
+}
+
+/// Blocks until a signal arrives, returns `false` if the pipe is broken
+/// (i.e. no signals can be received anymore).
+fn wait(rd: RawFd) -> bool {
+    let mut buf = [0u8; 1];
+    loop {
+        // SAFETY: `buf` is valid for writes of 1 byte, `rd` stays open forever
+        let ret = unsafe { libc::read(rd, buf.as_mut_ptr() as *mut libc::c_void, 1) };
+        if ret == 1 {
+            return true;
+        }
+        if ret < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+        return false;
+    }
+}
+
+/// Calls `f` (in a separate thread) once SIGINT or SIGTERM is received.
+///
+/// The signal handler only writes to a pipe (which is async-signal-safe),
+/// the thread which waits on it does the actual work.
+pub fn on_termination(f: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    let rd = pipe(&TERM_WR)?;
+    // a second signal terminates the process right away,
+    // in case the graceful shutdown hangs
+    install(libc::SIGINT, handle_term, libc::SA_RESETHAND)?;
+    install(libc::SIGTERM, handle_term, libc::SA_RESETHAND)?;
+
+    std::thread::spawn(move || {
+        if wait(rd) {
+            f();
+        }
+    });
+    Ok(())
+}
+
+/// Calls `f` (in a separate thread) every time SIGHUP is received.
+pub fn on_reload(mut f: impl FnMut() + Send + 'static) -> io::Result<()> {
+    let rd = pipe(&RELOAD_WR)?;
+    install(libc::SIGHUP, handle_reload, 0)?;
+
+    std::thread::spawn(move || {
+        while wait(rd) {
+            f();
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reload() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        on_reload(move || tx.send(()).unwrap()).unwrap();
+        for _ in 0..2 {
+            // SAFETY: the handler of SIGHUP is installed, thus the test process survives
+            assert_eq!(unsafe { libc::raise(libc::SIGHUP) }, 0);
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert!(rx.try_recv().is_err());
+    }
+}
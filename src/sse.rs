@@ -0,0 +1,62 @@
+//! server side of Server-Sent Events
+//! (see https://html.spec.whatwg.org/multipage/server-sent-events.html)
+
+use crate::http::{self, HeaderValue, StatusCode};
+use crossbeam_channel as chan;
+use std::io::{self, Write};
+
+/// Formats an event, `data` may contain newlines.
+pub fn event(data: &str) -> String {
+    let mut ret = String::with_capacity(data.len() + 8);
+    for line in data.split('\n') {
+        ret.push_str("data: ");
+        ret.push_str(line);
+        ret.push('\n');
+    }
+    ret.push('\n');
+    ret
+}
+
+/// comment which is ignored by clients, used to detect vanished ones
+pub const KEEPALIVE: &str = ":\n\n";
+
+/// Writes the response head and then the (already formatted) events
+/// until the channel gets closed or writing fails (i.e. the client is gone).
+pub fn stream(mut writer: impl Write, events: chan::Receiver<String>) -> io::Result<()> {
+    let mut res = http::response(StatusCode::OK, "text/event-stream", Vec::new());
+    res.headers_mut()
+        .insert("Cache-Control", HeaderValue::from_static("no-cache"));
+    http::write_head(&mut writer, &res)?;
+    for i in events {
+        writer.write_all(i.as_bytes())?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event() {
+        assert_eq!(event(r#"{"nodes":[]}"#), "data: {\"nodes\":[]}\n\n");
+        assert_eq!(event("a\nb"), "data: a\ndata: b\n\n");
+    }
+
+    #[test]
+    fn test_stream() {
+        let (s, r) = chan::unbounded();
+        s.send(event("1")).unwrap();
+        s.send(KEEPALIVE.to_string()).unwrap();
+        drop(s);
+        let mut out = Vec::new();
+        stream(&mut out, r).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+             cache-control: no-cache\r\n\r\ndata: 1\n\n:\n\n"
+        );
+    }
+}
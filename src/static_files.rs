@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+/// A directory of static files (e.g. the web frontend) which is served
+/// next to the WebSocket endpoint.
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(root: PathBuf) -> StaticFiles {
+        StaticFiles { root }
+    }
+
+    /// Maps the resource of a request to a path inside of the directory,
+    /// `/` and directories map to their `index.html`.
+    ///
+    /// Returns `None` if the resource could escape the directory
+    /// (`..`, backslashes, absolute paths) or refers to a hidden file.
+    /// Percent-encoded characters aren't decoded, thus they can't be
+    /// used to sneak in any of these.
+    pub fn resolve(&self, resource: &str) -> Option<PathBuf> {
+        let path = resource.split(['?', '#']).next()?;
+        let path = path.strip_prefix('/')?;
+        let mut ret = self.root.clone();
+        for comp in path.split('/').filter(|i| !i.is_empty()) {
+            if comp.starts_with('.') || comp.contains(['\\', ':', '\0']) {
+                return None;
+            }
+            ret.push(comp);
+        }
+        if ret.is_dir() {
+            ret.push("index.html");
+        }
+        Some(ret)
+    }
+
+    /// Reads the file which belongs to the resource of a request.
+    pub fn get(&self, resource: &str) -> Option<(&'static str, Vec<u8>)> {
+        let path = self.resolve(resource)?;
+        let data = std::fs::read(&path).ok()?;
+        Some((content_type(&path), data))
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|i| i.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let sf = StaticFiles::new("/srv/web".into());
+        assert_eq!(
+            sf.resolve("/vis.js?v=2"),
+            Some(PathBuf::from("/srv/web/vis.js"))
+        );
+        assert_eq!(
+            sf.resolve("/css//main.css"),
+            Some(PathBuf::from("/srv/web/css/main.css"))
+        );
+        assert_eq!(sf.resolve("/../etc/passwd"), None);
+        assert_eq!(sf.resolve("/css/../../etc/passwd"), None);
+        assert_eq!(sf.resolve("/..\\etc\\passwd"), None);
+        assert_eq!(sf.resolve("/.git/config"), None);
+        assert_eq!(sf.resolve("relative"), None);
+        assert_eq!(
+            sf.resolve("/%2e%2e/etc/passwd"),
+            Some(PathBuf::from("/srv/web/%2e%2e/etc/passwd"))
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let root = std::env::temp_dir().join(format!("bird2topo-static-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("index.html"), "<html></html>").unwrap();
+        let sf = StaticFiles::new(root.clone());
+        assert_eq!(
+            sf.get("/"),
+            Some(("text/html; charset=utf-8", b"<html></html>".to_vec()))
+        );
+        assert_eq!(sf.get("/missing.js"), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,10 +1,13 @@
 use bit_set::BitSet;
 use crossbeam_channel as chan;
-use std::sync::{Arc, Mutex};
-use tracing::{debug, error};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, error, warn};
 
 pub type TokenValue = usize;
 
+/// default maximum number of simultaneously handed out tokens
+pub const DEFAULT_CAPACITY: usize = (u16::MAX - 1) as usize;
+
 #[derive(Debug)]
 pub enum TokenUpdate<T> {
     Acquire(TokenValue, T),
@@ -12,7 +15,9 @@ pub enum TokenUpdate<T> {
 }
 
 struct TokensInner<T> {
+    /// free tokens
     data: Mutex<BitSet<u64>>,
+    capacity: usize,
     evc: chan::Sender<TokenUpdate<T>>,
 }
 
@@ -24,20 +29,30 @@ pub struct TokenGuard<T> {
     tokval: TokenValue,
 }
 
+impl<T> TokensInner<T> {
+    /// Locks the free tokens, a poisoned lock is recovered
+    /// (the bitset is always left in a consistent state).
+    fn lock(&self) -> MutexGuard<'_, BitSet<u64>> {
+        self.data.lock().unwrap_or_else(|x| {
+            warn!("tokens are poisoned, recovering");
+            x.into_inner()
+        })
+    }
+}
+
+impl<T> TokenGuard<T> {
+    pub fn value(&self) -> TokenValue {
+        self.tokval
+    }
+}
+
 impl<T> Drop for TokenGuard<T> {
     fn drop(&mut self) {
         let tv = self.tokval;
-        let mut is_success = false;
-        if let Ok(mut tks) = self.parent.data.lock() {
-            if tks.insert(tv) {
-                is_success = true;
-            } else {
-                error!("unable to return token {}", tv);
-            }
+        let is_success = self.parent.lock().insert(tv);
+        if !is_success {
+            error!("unable to return token {}", tv);
         } else {
-            error!("tokens are poisoned");
-        }
-        if is_success {
             let _ = self.parent.evc.send(TokenUpdate::Release(tv));
             debug!("released token {}", tv);
         }
@@ -46,24 +61,39 @@ impl<T> Drop for TokenGuard<T> {
 
 impl<T> Tokens<T> {
     pub fn new(evc: chan::Sender<TokenUpdate<T>>) -> Self {
-        let ibs: BitSet<_> = (0..(u16::MAX - 1) as TokenValue).into_iter().collect();
+        Self::with_capacity(DEFAULT_CAPACITY, evc)
+    }
+
+    pub fn with_capacity(capacity: usize, evc: chan::Sender<TokenUpdate<T>>) -> Self {
+        let ibs: BitSet<_> = (0..capacity as TokenValue).collect();
         Tokens(Arc::new(TokensInner {
             data: Mutex::new(ibs),
+            capacity,
             evc,
         }))
     }
 
+    /// maximum number of simultaneously handed out tokens
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+
+    /// number of currently handed out tokens
+    pub fn active(&self) -> usize {
+        let free = self.0.lock().len();
+        self.0.capacity.saturating_sub(free)
+    }
+
     pub fn try_acquire(&self, data: T) -> Result<TokenGuard<T>, T> {
-        let tokval = match self.0.data.lock() {
-            Ok(mut tks) => {
-                if let Some(tokval) = tks.iter().next() {
+        let tokval = {
+            let mut tks = self.0.lock();
+            match tks.iter().next() {
+                Some(tokval) => {
                     tks.remove(tokval);
                     tokval
-                } else {
-                    return Err(data);
                 }
+                None => return Err(data),
             }
-            Err(_) => return Err(data),
         };
         let _ = self.0.evc.send(TokenUpdate::Acquire(tokval, data));
         Ok(TokenGuard {
@@ -72,3 +102,54 @@ impl<T> Tokens<T> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active() {
+        let (s, _r) = chan::unbounded();
+        let tokens = Tokens::new(s);
+        assert_eq!(tokens.active(), 0);
+        let a = tokens.try_acquire(()).unwrap();
+        let b = tokens.try_acquire(()).unwrap();
+        assert_ne!(a.value(), b.value());
+        assert_eq!(tokens.active(), 2);
+        drop(a);
+        assert_eq!(tokens.active(), 1);
+        drop(b);
+        assert_eq!(tokens.active(), 0);
+        assert_eq!(tokens.capacity(), 65534);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let (s, _r) = chan::unbounded();
+        let tokens = Tokens::with_capacity(3, s);
+        let guards: Vec<_> = (0..3).map(|i| tokens.try_acquire(i).unwrap()).collect();
+        assert_eq!(tokens.try_acquire(3).err(), Some(3));
+        drop(guards);
+        assert!(tokens.try_acquire(4).is_ok());
+    }
+
+    #[test]
+    fn test_poisoned() {
+        let (s, _r) = chan::unbounded();
+        let tokens = Tokens::with_capacity(2, s);
+        let held = tokens.try_acquire(0).unwrap();
+        let tokens2 = tokens.clone();
+        assert!(std::thread::spawn(move || {
+            let _tks = tokens2.0.data.lock().unwrap();
+            panic!("poison the tokens");
+        })
+        .join()
+        .is_err());
+        assert!(tokens.0.data.is_poisoned());
+
+        assert!(tokens.try_acquire(1).is_ok());
+        drop(held);
+        assert_eq!(tokens.active(), 0);
+        assert!(tokens.try_acquire(2).is_ok());
+    }
+}
@@ -0,0 +1,111 @@
+//! Parses the `birdc show ospf state all` outputs in `tests/fixtures`,
+//! and compares the resulting graphs against the expected `.json` files.
+//!
+//! The outputs aren't captures of real networks: they were written by hand
+//! in the format of BIRD 2.0.7, using private and documentation addresses.
+//! Anonymised captures of real setups are welcome as additional fixtures.
+//!
+//! After intentional changes of the graph, the expected files can be
+//! regenerated via `BIRD2TOPO_BLESS=1 cargo test --test fixtures`.
+
+use bird2topo::gather::{gather, Source};
+use bird2topo::parser::{parse_topology, router2id, Topology, TopologyStats};
+use bird2topo::Graph;
+use std::path::PathBuf;
+
+/// relative to the package root (which is the working directory of the
+/// tests), because the paths end up in the graph as protocol names
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from("tests/fixtures").join(name)
+}
+
+fn check_graph(name: &str) -> Graph {
+    let source = Source::Files(vec![fixture(&format!("{}.txt", name))]);
+    let graph = gather(&source, &Default::default()).unwrap();
+    let expected_path = fixture(&format!("{}.json", name));
+    if std::env::var_os("BIRD2TOPO_BLESS").is_some() {
+        let mut json = serde_json::to_string_pretty(&graph).unwrap();
+        json.push('\n');
+        std::fs::write(&expected_path, json).unwrap();
+    }
+    let expected: Graph =
+        serde_json::from_str(&std::fs::read_to_string(&expected_path).unwrap()).unwrap();
+    assert_eq!(graph, expected, "graph of {} changed", name);
+    graph
+}
+
+fn stats(name: &str) -> TopologyStats {
+    let s = std::fs::read_to_string(fixture(&format!("{}.txt", name))).unwrap();
+    let topo = parse_topology(Topology::new(), &s).unwrap();
+    // the dump of the parsed topology reproduces it
+    let dump = topo.to_string();
+    assert_eq!(
+        parse_topology(Topology::new(), &dump).unwrap().to_string(),
+        dump
+    );
+    topo.stats()
+}
+
+fn node<'a>(graph: &'a Graph, label: &str) -> &'a bird2topo::gather::Node {
+    graph.nodes.iter().find(|i| i.label == label).unwrap()
+}
+
+#[test]
+fn v2_single_area() {
+    let graph = check_graph("v2-single-area");
+    assert_eq!(graph.bird_version.as_deref(), Some("2.0.7"));
+    assert_eq!(node(&graph, "10.0.0.1").group, "asbr");
+    assert_eq!(node(&graph, "10.0.0.0/24").group, "network");
+    assert_eq!(node(&graph, "192.168.3.0/24").group, "stubnet");
+    let default = router2id("0.0.0.0/0");
+    let ext: Vec<_> = graph
+        .edges
+        .iter()
+        .filter(|i| i.to == default || i.from == default)
+        .collect();
+    assert_eq!(ext.len(), 1);
+    assert!(ext[0].external);
+    assert_eq!(ext[0].metric, 10000);
+
+    let stats = stats("v2-single-area");
+    assert_eq!((stats.areas, stats.routers, stats.networks), (1, 3, 1));
+    assert_eq!(stats.unreachable, 0);
+    assert_eq!(stats.diameters["0.0.0.0"], 30);
+}
+
+#[test]
+fn v2_multi_area() {
+    let graph = check_graph("v2-multi-area");
+    assert_eq!(graph.bird_version.as_deref(), Some("2.0.8"));
+    let abr = node(&graph, "10.0.0.2");
+    assert_eq!(abr.group, "abr");
+    assert_eq!(
+        abr.details["areas"],
+        serde_json::json!(["0.0.0.0", "0.0.0.1"])
+    );
+    assert_eq!(node(&graph, "10.1.0.9").group, "asbr");
+    assert_eq!(node(&graph, "10.0.0.7").group, "unreachable");
+    let mut areas: Vec<_> = graph.edges.iter().map(|i| &i.area[..]).collect();
+    areas.sort();
+    areas.dedup();
+    assert_eq!(areas, ["0.0.0.0", "0.0.0.1"]);
+
+    let stats = stats("v2-multi-area");
+    assert_eq!((stats.areas, stats.routers, stats.networks), (2, 4, 1));
+    assert_eq!(stats.unreachable, 1);
+    assert_eq!(stats.diameters["0.0.0.0"], 10);
+    assert_eq!(stats.diameters["0.0.0.1"], 10);
+}
+
+#[test]
+fn v3_single_area() {
+    let graph = check_graph("v3-single-area");
+    assert_eq!(node(&graph, "[10.0.0.1-3]").group, "network");
+    assert_eq!(node(&graph, "2001:db8:1::/64").group, "stubnet");
+    assert_eq!(node(&graph, "10.0.0.4").group, "unreachable");
+
+    let stats = stats("v3-single-area");
+    assert_eq!((stats.areas, stats.routers, stats.networks), (1, 3, 1));
+    assert_eq!(stats.unreachable, 1);
+    assert_eq!(stats.diameters["0.0.0.0"], 10);
+}